@@ -1,6 +1,9 @@
 use crate::{
     enemies::{move_enemies, Enemy},
+    game_time::{GameTick, GameTime, TickStep},
     gold::{Gold, MouseFollow},
+    rng::GameRng,
+    status::StatusEffects,
 };
 use bevy::prelude::*;
 use rand::prelude::*;
@@ -9,9 +12,20 @@ pub struct BoidsPlugin;
 
 impl Plugin for BoidsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(boids_gold)
-            .add_system(boids_enemy)
-            .add_system(move_boids.before(move_enemies));
+        app.add_system_set_to_stage(
+            GameTick,
+            TickStep::Steer
+                .set()
+                .with_system(boids_gold)
+                // they share a random stream
+                .with_system(boids_enemy.after(boids_gold)),
+        )
+        .add_system_set_to_stage(
+            GameTick,
+            TickStep::Move
+                .set()
+                .with_system(move_boids.before(move_enemies)),
+        );
     }
 }
 
@@ -28,7 +42,10 @@ impl Boid {
     }
 }
 
-fn boids_gold(mut q_gold: Query<(&Transform, &mut Boid), (Without<MouseFollow>, With<Gold>)>) {
+fn boids_gold(
+    mut q_gold: Query<(&Transform, &mut Boid), (Without<MouseFollow>, With<Gold>)>,
+    mut rng: ResMut<GameRng>,
+) {
     // can't double loop the same query
     let mut combinations = q_gold.iter_combinations_mut();
     while let Some([a, b]) = combinations.fetch_next() {
@@ -44,8 +61,7 @@ fn boids_gold(mut q_gold: Query<(&Transform, &mut Boid), (Without<MouseFollow>,
                                          // could've just been d<0.1
             if dir.x.abs() < 0.1 && dir.y.abs() < 0.1 {
                 // close enough to 0
-                let mut rng = rand::thread_rng();
-                dir = Vec2::new(rng.gen(), rng.gen());
+                dir = Vec2::new(rng.boids.gen(), rng.boids.gen());
             }
             dir = dir.normalize_or_zero() * (d + 0.01); // for when d = 0
 
@@ -55,7 +71,7 @@ fn boids_gold(mut q_gold: Query<(&Transform, &mut Boid), (Without<MouseFollow>,
     }
 }
 
-fn boids_enemy(mut q_enemy: Query<(&Transform, &mut Boid), With<Enemy>>) {
+fn boids_enemy(mut q_enemy: Query<(&Transform, &mut Boid), With<Enemy>>, mut rng: ResMut<GameRng>) {
     let mut combinations = q_enemy.iter_combinations_mut();
     while let Some([a, b]) = combinations.fetch_next() {
         // mutably access components data
@@ -70,8 +86,7 @@ fn boids_enemy(mut q_enemy: Query<(&Transform, &mut Boid), With<Enemy>>) {
 
             if d < 0.01 {
                 // close enough to 0
-                let mut rng = rand::thread_rng();
-                dir = Vec2::new(rng.gen(), rng.gen());
+                dir = Vec2::new(rng.boids.gen(), rng.boids.gen());
             }
             dir = dir.normalize_or_zero() * (d + 0.01); // for when d = 0
 
@@ -81,10 +96,15 @@ fn boids_enemy(mut q_enemy: Query<(&Transform, &mut Boid), With<Enemy>>) {
     }
 }
 
-fn move_boids(mut q_boid: Query<(&mut Transform, &mut Boid)>, time: Res<Time>) {
-    for (mut trans, mut boid) in q_boid.iter_mut() {
+fn move_boids(
+    mut q_boid: Query<(&mut Transform, &mut Boid, Option<&StatusEffects>)>,
+    time: Res<GameTime>,
+) {
+    for (mut trans, mut boid, effects) in q_boid.iter_mut() {
+        // stuck in place means not shoved around either
+        let speed = effects.map_or(1.0, |e| e.speed());
         trans.translation +=
-            boid.sep_dir.normalize_or_zero().extend(0.0) * time.delta_seconds() * 60.0;
+            boid.sep_dir.normalize_or_zero().extend(0.0) * time.delta_seconds() * 60.0 * speed;
         boid.sep_dir = Vec2::ZERO;
     }
 }
@@ -0,0 +1,204 @@
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+
+use crate::enemies::{Boss, BossSpawnEvent};
+use crate::hex::{HexCollection, HexCoords};
+use crate::{GameState, HEIGHT};
+
+const PAN_SPEED: f32 = 500.0;
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 2.0;
+// how much one notch of the scroll wheel zooms
+const ZOOM_STEP: f32 = 0.1;
+// room to look past the edge of the board
+// enemies spawn out past it
+const BOUNDS_MARGIN: f32 = 250.0;
+// how quickly the camera catches up to the boss
+const FOLLOW_SPEED: f32 = 3.0;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(CameraControl::default())
+            .add_system(update_bounds)
+            .add_system(follow_boss_input)
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(reset_camera))
+            .add_system(move_camera.after(update_bounds));
+    }
+}
+
+/// How the camera is being moved around
+pub struct CameraControl {
+    // the camera center stays inside this box
+    min: Vec2,
+    max: Vec2,
+    // F toggles following the boss when it shows up
+    pub follow_boss: bool,
+    following: bool,
+}
+
+impl Default for CameraControl {
+    fn default() -> Self {
+        CameraControl {
+            min: Vec2::splat(-BOUNDS_MARGIN),
+            max: Vec2::splat(BOUNDS_MARGIN),
+            follow_boss: false,
+            following: false,
+        }
+    }
+}
+
+impl CameraControl {
+    pub fn clamp(&self, pos: Vec2) -> Vec2 {
+        pos.clamp(self.min, self.max)
+    }
+}
+
+/// Box around every hex center, plus some room around the edge
+pub fn board_bounds(hexes: impl Iterator<Item = HexCoords>) -> (Vec2, Vec2) {
+    let mut min = Vec2::ZERO;
+    let mut max = Vec2::ZERO;
+    for c in hexes {
+        let p = c.to_position();
+        min = min.min(p);
+        max = max.max(p);
+    }
+    (min - BOUNDS_MARGIN, max + BOUNDS_MARGIN)
+}
+
+// the board grows over time
+fn update_bounds(mut control: ResMut<CameraControl>, hex_collect: Res<HexCollection>) {
+    if hex_collect.is_changed() {
+        let (min, max) = board_bounds(hex_collect.hexes.keys().copied());
+        control.min = min;
+        control.max = max;
+    }
+}
+
+fn follow_boss_input(
+    mut control: ResMut<CameraControl>,
+    keys: Res<Input<KeyCode>>,
+    mut ev_boss_spawn: EventReader<BossSpawnEvent>,
+) {
+    if keys.just_pressed(KeyCode::F) {
+        control.follow_boss = !control.follow_boss;
+        control.following = control.follow_boss;
+    }
+
+    for _ev in ev_boss_spawn.iter() {
+        if control.follow_boss {
+            control.following = true;
+        }
+    }
+}
+
+// back to the middle for a new game
+fn reset_camera(
+    mut control: ResMut<CameraControl>,
+    mut q_camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+    control.following = false;
+    for (mut trans, mut projection) in q_camera.iter_mut() {
+        trans.translation.x = 0.0;
+        trans.translation.y = 0.0;
+        projection.scale = 1.0;
+    }
+}
+
+pub fn move_camera(
+    mut control: ResMut<CameraControl>,
+    mut q_camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+    q_boss: Query<&Transform, (With<Boss>, Without<Camera>)>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    mut ev_wheel: EventReader<MouseWheel>,
+    mut ev_motion: EventReader<MouseMotion>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+) {
+    let (mut trans, mut projection) = q_camera.single_mut();
+
+    // zoom
+    for ev in ev_wheel.iter() {
+        let notches = match ev.unit {
+            MouseScrollUnit::Line => ev.y,
+            // trackpads scroll in pixels
+            MouseScrollUnit::Pixel => ev.y / 20.0,
+        };
+        projection.scale *= 1.0 - notches * ZOOM_STEP;
+    }
+    if keys.pressed(KeyCode::Equals) {
+        projection.scale *= 1.0 - time.delta_seconds();
+    }
+    if keys.pressed(KeyCode::Minus) {
+        projection.scale *= 1.0 + time.delta_seconds();
+    }
+    projection.scale = projection.scale.clamp(MIN_ZOOM, MAX_ZOOM);
+
+    // pan
+    let mut dir = Vec2::ZERO;
+    if keys.any_pressed([KeyCode::W, KeyCode::Up]) {
+        dir.y += 1.0;
+    }
+    if keys.any_pressed([KeyCode::S, KeyCode::Down]) {
+        dir.y -= 1.0;
+    }
+    if keys.any_pressed([KeyCode::A, KeyCode::Left]) {
+        dir.x -= 1.0;
+    }
+    if keys.any_pressed([KeyCode::D, KeyCode::Right]) {
+        dir.x += 1.0;
+    }
+    // move the same speed on screen at any zoom
+    let mut delta = dir.normalize_or_zero() * PAN_SPEED * projection.scale * time.delta_seconds();
+
+    // drag with the middle mouse button
+    let motion = ev_motion.iter().fold(Vec2::ZERO, |sum, ev| sum + ev.delta);
+    if buttons.pressed(MouseButton::Middle) {
+        if let Some(win) = windows.get_primary() {
+            // screen pixels to world units
+            let pixel = HEIGHT / win.height() * projection.scale;
+            // screen y points down
+            delta += Vec2::new(-motion.x, motion.y) * pixel;
+        }
+    }
+
+    let mut pos = trans.translation.truncate();
+    if delta != Vec2::ZERO {
+        // moving by hand stops following
+        control.following = false;
+        pos += delta;
+    } else if control.following {
+        if let Some(boss) = q_boss.iter().next() {
+            let target = boss.translation.truncate();
+            pos = pos.lerp(target, (FOLLOW_SPEED * time.delta_seconds()).min(1.0));
+        }
+    }
+
+    let pos = control.clamp(pos);
+    trans.translation.x = pos.x;
+    trans.translation.y = pos.y;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_cover_the_board() {
+        let board = HexCoords::new().spiral(4);
+        let (min, max) = board_bounds(board.iter().copied());
+        for c in board {
+            let p = c.to_position();
+            // with the margin left over around the edge
+            let inner_min = min + BOUNDS_MARGIN - 0.01;
+            let inner_max = max - BOUNDS_MARGIN + 0.01;
+            assert!(p.cmpge(inner_min).all() && p.cmple(inner_max).all());
+        }
+
+        // a bigger board has bigger bounds
+        let (big_min, big_max) = board_bounds(HexCoords::new().spiral(10).into_iter());
+        assert!(big_min.cmplt(min).all() && big_max.cmpgt(max).all());
+    }
+}
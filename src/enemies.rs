@@ -1,16 +1,41 @@
-use bevy::utils::Duration;
-use bevy::{prelude::*, utils::FloatOrd};
+use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
-use rand::prelude::*;
 
 use crate::boids::Boid;
+use crate::enemy_types::{Behaviour, EnemyType, EnemyTypes};
+use crate::game_assets::GameAssets;
+use crate::game_time::{interpolate, GameTick, GameTime, Interpolated, TickStep};
 use crate::gold::GoldPile;
-use crate::tower::bullet_hit;
-use crate::StartSpawningEnemiesEvent;
-use crate::{gold::Gold, palette::*};
+use crate::hex::{Hex, HexCollection, HexCoords};
+use crate::lives::GoldEscapedEvent;
+use crate::pathfinding::FlowField;
+use crate::status::{StatusEffects, StatusHit};
+use crate::GameState;
+use crate::{gold::Gold, tower};
 
-const ENEMY_SPAWN_TIME: f32 = 10.0;
-const BOSS_HEALTH: u32 = 750; //1000
+pub const BOSS_HEALTH: u32 = 750; //1000
+
+// seconds from full to beaten
+pub const BOSS_DEFEAT_TIME: f32 = 2.0;
+
+/// How full the boss is when it does each move
+pub const BOSS_PHASES: [(f32, BossMove); 3] = [
+    (0.25, BossMove::Minions),
+    (0.5, BossMove::Steal),
+    (0.75, BossMove::Minions),
+];
+
+const BOSS_SPEED: f32 = 12.0;
+// it stops this far out, so the treasury isn't under it
+const BOSS_STOP_DISTANCE: f32 = 160.0;
+// called in all around it
+const BOSS_MINIONS: [&str; 6] = ["grunt", "grunt", "grunt", "runner", "runner", "tank"];
+// a steal takes this much from every pile in reach
+const BOSS_STEAL: u32 = 5;
+const BOSS_STEAL_RADIUS: f32 = 250.0;
+
+// enemies holding gold get away once they're this far from the center
+const ESCAPE_DISTANCE: f32 = 700.0;
 
 pub struct EnemyPlugin;
 
@@ -19,20 +44,53 @@ impl Plugin for EnemyPlugin {
         app.add_event::<SpawnEnemyEvent>()
             .add_event::<BossSpawnEvent>()
             .add_event::<BossCapEvent>()
-            .insert_resource(EnemySpawnInfo { group_size: 5 })
-            .add_system(setup)
-            .add_system(generate_enemies)
+            .add_event::<DamageEvent>()
+            .init_resource::<EnemyTypes>()
             .add_system(spawn_enemy)
-            .add_system(move_enemies)
-            .add_system(move_shadow.after(move_enemies))
-            //.add_system(grab_gold.before(bullet_hit))
-            .add_system(escape)
-            .add_system(spawn_boss)
-            // bullet_hit adds Dead. Run before it so it runs next frame
+            // the tower that calls the boss is paid for first
+            .add_system(spawn_boss.after(tower::preview_paid_for))
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(reset_enemies))
+            // bullet_hit adds Dead. Run first thing in the next tick
             // and then this entity won't be added to any other queries
             // what's the pattern?
             // run die code before the thing that sets Dead?
-            .add_system(drop_gold_and_die.before(bullet_hit));
+            .add_system_set_to_stage(
+                GameTick,
+                TickStep::Cleanup.set().with_system(drop_gold_and_die),
+            )
+            .add_system_set_to_stage(GameTick, TickStep::Timers.set().with_system(defeat_boss))
+            .add_system_set_to_stage(
+                GameTick,
+                TickStep::Move
+                    .set()
+                    .with_system(move_enemies)
+                    .with_system(move_boss),
+            )
+            .add_system_set_to_stage(
+                GameTick,
+                TickStep::Hit.set().with_system(
+                    take_damage
+                        .after(tower::bullet_hit)
+                        .after(tower::tick_bomb_explosion),
+                ),
+            )
+            //.add_system(grab_gold.before(bullet_hit))
+            // after anything that kills, so it doesn't despawn
+            // an enemy that's about to get Dead added
+            .add_system_set_to_stage(
+                GameTick,
+                TickStep::Escape
+                    .set()
+                    .with_system(escape)
+                    // after the gold has landed on it this tick
+                    .with_system(boss_phases),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    // follows where the enemy is drawn
+                    .with_system(move_shadow.after(interpolate))
+                    .with_system(float_damage_numbers),
+            );
     }
 }
 
@@ -40,110 +98,196 @@ impl Plugin for EnemyPlugin {
 pub struct Enemy {
     pub has_gold: bool,
     pub dir: Vec2,
+    pub mode: MoveMode,
+    // which EnemyType it is
+    pub kind: String,
+    pub speed: f32,
+    pub size: f32,
+    // gold it takes from a pile at once
+    pub carry: u32,
 }
 
 impl Enemy {
-    fn new() -> Self {
+    pub fn new(kind: &EnemyType) -> Self {
         Enemy {
             has_gold: false,
             dir: Vec2::ZERO,
+            mode: if kind.has(&Behaviour::Direct) {
+                MoveMode::Direct
+            } else {
+                MoveMode::FollowPath
+            },
+            kind: kind.id.clone(),
+            speed: kind.speed,
+            size: kind.size,
+            carry: kind.carry,
         }
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MoveMode {
+    // straight at the target, through towers and all
+    Direct,
+    // walk around towers and blocked hexes
+    FollowPath,
+}
+
 #[derive(Component)]
 pub struct Dead;
 
 #[derive(Component)]
-struct EnemySpawner {
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub fn new(max: u32) -> Self {
+        Health { current: max, max }
+    }
+}
+
+/// Taken off every hit. A hit always does at least 1 damage
+#[derive(Component)]
+pub struct Armor(pub u32);
+
+/// A hit on an enemy, before armor
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: u32,
+    // put on it as well
+    pub status: Option<StatusHit>,
+}
+
+// how long a damage number floats before it's gone
+const DAMAGE_NUMBER_TIME: f32 = 0.6;
+
+#[derive(Component)]
+struct DamageNumber {
     timer: Timer,
 }
 
+/// Walks in on the center. Feeding it gold is the only way to beat it
 #[derive(Component)]
-pub struct Boss;
+pub struct Boss {
+    // how many of BOSS_PHASES it's been through
+    pub phase: usize,
+    // full up. Counts down to BossCapEvent
+    pub defeat: Option<Timer>,
+}
+
+impl Boss {
+    pub fn new() -> Self {
+        Boss {
+            phase: 0,
+            defeat: None,
+        }
+    }
+}
+
+/// What the boss does as it fills up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BossMove {
+    // calls in BOSS_MINIONS around itself
+    Minions,
+    // eats gold out of the piles around it
+    Steal,
+}
 
 pub struct BossSpawnEvent;
 pub struct BossCapEvent;
 
 pub struct SpawnEnemyEvent {
     pub position: Vec3,
+    // an EnemyType id
+    pub kind: String,
 }
 
-struct EnemySpawnInfo {
-    group_size: u32,
+impl SpawnEnemyEvent {
+    pub fn new(position: Vec3, kind: &str) -> Self {
+        SpawnEnemyEvent {
+            position,
+            kind: kind.to_string(),
+        }
+    }
 }
 
-fn setup(mut commands: Commands, mut ev_start: EventReader<StartSpawningEnemiesEvent>) {
-    for _ev in ev_start.iter() {
-        commands.spawn().insert(EnemySpawner {
-            timer: Timer::new(Duration::from_secs_f32(ENEMY_SPAWN_TIME), true),
-        });
-    }
+/// Breaks into this many smaller enemies when it dies
+#[derive(Component)]
+struct Splits {
+    into: String,
+    count: u32,
 }
 
-fn generate_enemies(
-    time: Res<Time>,
-    mut ev_spawn_enemy: EventWriter<SpawnEnemyEvent>,
-    mut q_spawner: Query<&mut EnemySpawner>,
-    mut info: ResMut<EnemySpawnInfo>,
+pub fn spawn_enemy(
+    mut commands: Commands,
+    mut ev_spawn_enemy: EventReader<SpawnEnemyEvent>,
+    types: Res<EnemyTypes>,
 ) {
-    for mut spawner in q_spawner.iter_mut() {
-        if spawner.timer.tick(time.delta()).finished() {
-            for _ in 0..info.group_size {
-                let mut rng = rand::thread_rng();
-                let spawn_pos = Vec2::new(rng.gen_range(-1.0..=1.0), rng.gen_range(-1.0..=1.0))
-                    .normalize_or_zero()
-                    * 500.;
-
-                ev_spawn_enemy.send(SpawnEnemyEvent {
-                    position: spawn_pos.extend(0.3),
-                })
+    for ev in ev_spawn_enemy.iter() {
+        match types.get(&ev.kind) {
+            Some(kind) => {
+                spawn_enemy_at(&mut commands, ev.position, kind);
             }
-            info.group_size += 1;
+            None => warn!("no enemy type called {}", ev.kind),
         }
     }
 }
 
-fn spawn_enemy(mut commands: Commands, mut ev_spawn_enemy: EventReader<SpawnEnemyEvent>) {
-    for ev in ev_spawn_enemy.iter() {
-        let e = commands
-            .spawn_bundle(SpriteBundle {
-                sprite: Sprite {
-                    color: CRIMSON,
-                    custom_size: Some(Vec2::new(15.0, 15.0)),
-                    ..default()
-                },
-                transform: Transform {
-                    translation: ev.position,
-                    ..default()
-                },
+/// Spawns an enemy and its shadow
+pub fn spawn_enemy_at(commands: &mut Commands, position: Vec3, kind: &EnemyType) -> Entity {
+    let size = Vec2::splat(kind.size);
+    let e = commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: kind.color(),
+                custom_size: Some(size),
                 ..default()
-            })
-            .insert(Enemy::new())
-            .insert(Boid::new())
-            .insert(RigidBody::Dynamic)
-            .insert(Collider::cuboid(7.5, 7.5))
-            .insert(Sensor)
-            .id();
-
-        commands
-            .spawn_bundle(SpriteBundle {
-                sprite: Sprite {
-                    color: Color::BLACK,
-                    custom_size: Some(Vec2::new(15.0, 15.0)),
-                    ..default()
-                },
-                transform: Transform {
-                    translation: ev.position,
-                    ..default()
-                },
+            },
+            transform: Transform {
+                translation: position,
                 ..default()
-            })
-            .insert(Follow {
-                target: e,
-                offset: Vec3::new(1.0, -3.5, -0.1),
-            });
+            },
+            ..default()
+        })
+        .insert(Enemy::new(kind))
+        .insert(Health::new(kind.health))
+        .insert(StatusEffects::default())
+        .insert(Boid::new())
+        .insert(Interpolated::new(position))
+        .insert(Collider::cuboid(size.x / 2.0, size.y / 2.0))
+        .insert(Sensor)
+        .id();
+    if kind.armor > 0 {
+        commands.entity(e).insert(Armor(kind.armor));
+    }
+    if let Some((into, count)) = kind.split() {
+        commands.entity(e).insert(Splits {
+            into: into.to_string(),
+            count,
+        });
     }
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::BLACK,
+                custom_size: Some(size),
+                ..default()
+            },
+            transform: Transform {
+                translation: position,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Follow {
+            target: e,
+            offset: Vec3::new(1.0, -3.5, -0.1),
+        });
+
+    e
 }
 
 // can I do this in a different way to have things be able to chain follow?
@@ -176,65 +320,267 @@ struct Follow {
 fn spawn_boss(
     mut commands: Commands,
     mut ev_boss_spawn: EventReader<BossSpawnEvent>,
-    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
 ) {
     for _ev in ev_boss_spawn.iter() {
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("sprites/Monster.png"),
-                transform: Transform {
-                    translation: Vec3::new(400.0, 20.0, 0.2),
-                    ..default()
-                },
-                ..default()
-            })
-            .insert(Boss)
-            .insert(GoldPile {
+        spawn_boss_at(
+            &mut commands,
+            &game_assets,
+            Vec3::new(400.0, 20.0, 0.2),
+            GoldPile {
                 count: 0,
                 gold_cap: BOSS_HEALTH,
-            });
+            },
+            Boss::new(),
+        );
+    }
+}
+
+/// The boss carries its health around as a gold pile
+pub fn spawn_boss_at(
+    commands: &mut Commands,
+    game_assets: &GameAssets,
+    position: Vec3,
+    pile: GoldPile,
+    boss: Boss,
+) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: game_assets.monster.clone(),
+            transform: Transform {
+                translation: position,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(boss)
+        .insert(pile)
+        .insert(Interpolated::new(position))
+        .id()
+}
+
+fn move_boss(mut q_boss: Query<(&mut Transform, &Boss)>, time: Res<GameTime>) {
+    for (mut trans, boss) in q_boss.iter_mut() {
+        let pos = trans.translation.truncate();
+        let left = pos.length() - BOSS_STOP_DISTANCE;
+        if boss.defeat.is_some() || left <= 0.0 {
+            continue;
+        }
+        let step = (BOSS_SPEED * time.delta_seconds()).min(left);
+        trans.translation -= (pos.normalize() * step).extend(0.0);
+    }
+}
+
+fn boss_phases(
+    mut q_boss: Query<(&Transform, &GoldPile, &mut Boss)>,
+    mut q_piles: Query<(&Hex, &mut GoldPile), (Without<Boss>, Without<tower::TowerPreview>)>,
+    mut ev_spawn_enemy: EventWriter<SpawnEnemyEvent>,
+) {
+    for (trans, pile, mut boss) in q_boss.iter_mut() {
+        if boss.defeat.is_some() {
+            continue;
+        }
+        if pile.count >= pile.gold_cap {
+            boss.defeat = Some(Timer::from_seconds(BOSS_DEFEAT_TIME, false));
+            continue;
+        }
+
+        let pos = trans.translation.truncate();
+        let fill = pile.count as f32 / pile.gold_cap as f32;
+        // a big handful of gold can set off more than one
+        while let Some(&(at, boss_move)) = BOSS_PHASES.get(boss.phase) {
+            if fill < at {
+                break;
+            }
+            boss.phase += 1;
+            match boss_move {
+                BossMove::Minions => {
+                    for (i, kind) in BOSS_MINIONS.iter().enumerate() {
+                        let angle = i as f32 / BOSS_MINIONS.len() as f32 * std::f32::consts::TAU;
+                        let position = pos + Vec2::from_angle(angle) * 60.0;
+                        ev_spawn_enemy.send(SpawnEnemyEvent::new(position.extend(0.3), kind));
+                    }
+                }
+                BossMove::Steal => {
+                    for (hex, mut pile) in q_piles.iter_mut() {
+                        // the treasury is too well guarded
+                        if hex.coords == HexCoords::new() {
+                            continue;
+                        }
+                        if hex.coords.to_position().distance(pos) < BOSS_STEAL_RADIUS {
+                            pile.count -= pile.count.min(BOSS_STEAL);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// shrinks away, then the game is won
+fn defeat_boss(
+    mut commands: Commands,
+    mut q_boss: Query<(Entity, &mut Transform, &mut Boss)>,
+    time: Res<GameTime>,
+    mut ev_boss_cap: EventWriter<BossCapEvent>,
+) {
+    for (ent, mut trans, mut boss) in q_boss.iter_mut() {
+        if let Some(timer) = &mut boss.defeat {
+            timer.tick(time.delta());
+            trans.scale = Vec3::splat(1.0 - timer.percent());
+            if timer.just_finished() {
+                ev_boss_cap.send(BossCapEvent);
+                commands.entity(ent).despawn_recursive();
+            }
+        }
     }
 }
 
 pub fn move_enemies(
-    mut q_enemies: Query<(&mut Transform, &mut Enemy), Without<Dead>>,
-    q_gold: Query<&Transform, (With<Gold>, Without<Enemy>)>,
-    time: Res<Time>,
+    mut q_enemies: Query<(&mut Transform, &mut Enemy, Option<&StatusEffects>), Without<Dead>>,
+    q_hexes: Query<&Hex>,
+    hex_collect: Res<HexCollection>,
+    field: Res<FlowField>,
+    time: Res<GameTime>,
 ) {
-    for (mut trans, mut enemy) in q_enemies.iter_mut() {
-        let mut dir = Vec3::new(0.0, 0.0, 0.0) - trans.translation;
+    for (mut trans, mut enemy, effects) in q_enemies.iter_mut() {
+        let pos = trans.translation.truncate();
 
-        if enemy.has_gold {
-            dir = trans.translation - Vec3::ZERO;
-        } else {
-            let direction = q_gold
-                .iter()
-                .min_by_key(|target_transform| {
-                    FloatOrd(Vec3::distance(
-                        target_transform.translation,
-                        trans.translation,
-                    ))
-                })
-                .map(|closest_target| closest_target.translation - trans.translation);
-
-            if let Some(direction) = direction {
-                dir = direction;
+        // head for the center
+        // or run directly away from it with gold
+        let mut dir = if enemy.has_gold { pos } else { -pos };
+
+        if enemy.mode == MoveMode::FollowPath {
+            // walk around towers to the closest pile
+            // or the closest way off the map
+            let step = if enemy.has_gold {
+                field.edge_step(pos)
+            } else {
+                field.gold_step(pos)
+            };
+            if let Some(step) = step {
+                dir = step - pos;
             }
         }
 
-        dir.z = 0.0;
+        let dir = dir.extend(0.0);
         enemy.dir = dir.truncate();
 
-        trans.translation += dir.normalize_or_zero() * 100. * time.delta_seconds();
+        // terrain under the enemy can slow it down
+        let speed = hex_collect
+            .hexes
+            .get(&HexCoords::from_position(trans.translation.truncate()))
+            .and_then(|&e| q_hexes.get(e).ok())
+            .map_or(1.0, |hex| hex.terrain.enemy_speed())
+            // and so can being slowed or stunned
+            * effects.map_or(1.0, |e| e.speed());
+
+        trans.translation += dir.normalize_or_zero() * enemy.speed * speed * time.delta_seconds();
+    }
+}
+
+// several hits in a tick can land on the same enemy,
+// so Dead only goes on once and the rest are ignored
+fn take_damage(
+    mut commands: Commands,
+    mut ev_damage: EventReader<DamageEvent>,
+    mut q_enemies: Query<
+        (
+            &mut Health,
+            Option<&Armor>,
+            &Transform,
+            Option<&mut StatusEffects>,
+        ),
+        Without<Dead>,
+    >,
+    game_assets: Res<GameAssets>,
+) {
+    for ev in ev_damage.iter() {
+        let (mut health, armor, trans, mut effects) = match q_enemies.get_mut(ev.target) {
+            Ok(enemy) => enemy,
+            Err(_) => continue,
+        };
+        if health.current == 0 {
+            continue;
+        }
+        // marked enemies take more, then armor takes some off
+        let taken = effects.as_ref().map_or(1.0, |e| e.damage_taken());
+        let amount = (ev.amount as f32 * taken).round() as u32;
+        let amount = amount.saturating_sub(armor.map_or(0, |a| a.0)).max(1);
+        health.current = health.current.saturating_sub(amount);
+        if health.current == 0 {
+            commands.entity(ev.target).insert(Dead);
+        }
+        if let (Some(hit), Some(effects)) = (ev.status, effects.as_mut()) {
+            effects.apply(hit);
+        }
+
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::from_section(
+                    amount.to_string(),
+                    TextStyle {
+                        font: game_assets.font.clone(),
+                        font_size: 14.0,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_alignment(TextAlignment::CENTER),
+                transform: Transform::from_translation(
+                    trans.translation.truncate().extend(0.9) + Vec3::Y * 10.0,
+                ),
+                ..default()
+            })
+            .insert(DamageNumber {
+                timer: Timer::from_seconds(DAMAGE_NUMBER_TIME, false),
+            });
+    }
+}
+
+// drift up and fade out. Only for show, so on real time
+fn float_damage_numbers(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut q_numbers: Query<(Entity, &mut DamageNumber, &mut Transform, &mut Text)>,
+) {
+    for (ent, mut number, mut trans, mut text) in q_numbers.iter_mut() {
+        if number.timer.tick(time.delta()).finished() {
+            commands.entity(ent).despawn();
+            continue;
+        }
+        trans.translation.y += 30.0 * time.delta_seconds();
+        let alpha = 1.0 - number.timer.percent();
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(alpha);
+        }
     }
 }
 
 fn drop_gold_and_die(
     mut commands: Commands,
-    q_enemies: Query<(Entity, &Enemy, &Transform, Option<&Children>), Added<Dead>>,
+    q_enemies: Query<
+        (
+            Entity,
+            &Enemy,
+            &Transform,
+            Option<&Children>,
+            Option<&Splits>,
+        ),
+        Added<Dead>,
+    >,
     mut q_child: Query<(&mut Transform, &Sprite), Without<Enemy>>,
+    mut ev_spawn_enemy: EventWriter<SpawnEnemyEvent>,
 ) {
-    for (ent, enemy, e_trans, children) in q_enemies.iter() {
+    for (ent, enemy, e_trans, children, splits) in q_enemies.iter() {
+        if let Some(splits) = splits {
+            // spread around where it died
+            for i in 0..splits.count {
+                let angle = i as f32 / splits.count as f32 * std::f32::consts::TAU;
+                let offset = Vec2::from_angle(angle) * enemy.size / 2.0;
+                let position = (e_trans.translation.truncate() + offset).extend(0.3);
+                ev_spawn_enemy.send(SpawnEnemyEvent::new(position, &splits.into));
+            }
+        }
         if enemy.has_gold {
             if let Some(children) = children {
                 // print!("Has some children");
@@ -246,12 +592,15 @@ fn drop_gold_and_die(
                     // print!("Add Gold ");
                     // println!("child ent: {:?}", child);
                     // it's probably immediately colliding with gold
-                    commands.entity(child).insert(Gold);
+                    let position = e_trans.translation.truncate().extend(0.3);
+                    commands
+                        .entity(child)
+                        .insert(Gold)
+                        .insert(Interpolated::new(position));
                     let child_trans = q_child.get_mut(child);
                     match child_trans {
                         Ok(mut t) => {
-                            t.0.translation = e_trans.translation;
-                            t.0.translation.z = 0.3;
+                            t.0.translation = position;
                         }
                         Err(e) => {
                             error!("Error getting child transform: {e}");
@@ -275,12 +624,292 @@ fn drop_gold_and_die(
     }
 }
 
-fn escape(mut commands: Commands, q_enemies: Query<(Entity, &Enemy, &Transform), Without<Dead>>) {
-    for (ent, enemy, trans) in q_enemies.iter() {
-        if enemy.has_gold && trans.translation.distance(Vec3::ZERO) > 700.0 {
+fn escape(
+    mut commands: Commands,
+    q_enemies: Query<(Entity, &Enemy, &Health, &Transform, Option<&Children>), Without<Dead>>,
+    mut ev_escaped: EventWriter<GoldEscapedEvent>,
+) {
+    for (ent, enemy, health, trans, children) in q_enemies.iter() {
+        // killed this tick. Dead isn't on until the commands run,
+        // and it has to drop its gold, not take it along
+        if health.current == 0 {
+            continue;
+        }
+        if enemy.has_gold && trans.translation.truncate().length() > ESCAPE_DISTANCE {
             // escaped
             println!("Escaped");
+            // a life for every gold it got away with
+            let carried = children.map_or(1, |c| c.len().max(1));
+            for _ in 0..carried {
+                ev_escaped.send(GoldEscapedEvent);
+            }
             commands.entity(ent).despawn_recursive();
         }
     }
 }
+
+// back to the menu. Get rid of everything from the last game
+fn reset_enemies(
+    mut commands: Commands,
+    q_enemies: Query<Entity, Or<(With<Enemy>, With<Follow>, With<Boss>, With<DamageNumber>)>>,
+) {
+    for ent in q_enemies.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemy_types::GRUNT;
+    use crate::game_time::TICK;
+    use crate::lives::Lives;
+    use crate::status::Status;
+    use crate::test_app::TestApp;
+
+    // where test enemies come in
+    const START: Vec2 = Vec2::new(300.0, 0.0);
+
+    fn hit(test: &mut TestApp, target: Entity, amount: u32) {
+        test.send(DamageEvent {
+            target,
+            amount,
+            status: None,
+        });
+        test.ticks(1);
+    }
+
+    #[test]
+    fn enemies_live_until_their_health_runs_out() {
+        let mut test = TestApp::new(1);
+        let ent = test.spawn_enemy(START, GRUNT);
+        test.app.world.entity_mut(ent).insert(Health::new(25));
+
+        hit(&mut test, ent, 10);
+        hit(&mut test, ent, 10);
+        assert_eq!(test.get::<Health>(ent).unwrap().current, 5);
+        assert!(test.get::<Dead>(ent).is_none());
+
+        hit(&mut test, ent, 10);
+        assert!(test.get::<Dead>(ent).is_some());
+        test.ticks(1);
+        assert!(test.get::<Enemy>(ent).is_none(), "not despawned");
+    }
+
+    #[test]
+    fn armor_blunts_hits_but_never_stops_them() {
+        let mut test = TestApp::new(1);
+        let ent = test.spawn_enemy(START, GRUNT);
+        test.app
+            .world
+            .entity_mut(ent)
+            .insert(Health::new(20))
+            .insert(Armor(4));
+
+        hit(&mut test, ent, 10);
+        assert_eq!(test.get::<Health>(ent).unwrap().current, 14);
+        hit(&mut test, ent, 2);
+        assert_eq!(test.get::<Health>(ent).unwrap().current, 13);
+    }
+
+    #[test]
+    fn killed_on_the_way_out() {
+        let mut test = TestApp::new(1);
+        let ent = test.spawn_enemy(START, GRUNT);
+        let gold = test
+            .app
+            .world
+            .spawn()
+            .insert_bundle(SpriteBundle::default())
+            .id();
+        test.app.world.entity_mut(ent).push_children(&[gold]);
+        test.app.world.get_mut::<Enemy>(ent).unwrap().has_gold = true;
+        test.app
+            .world
+            .get_mut::<Transform>(ent)
+            .unwrap()
+            .translation = Vec3::new(ESCAPE_DISTANCE + 50.0, 0.0, 0.3);
+        let mut reader = test.resource::<Events<GoldEscapedEvent>>().get_reader();
+
+        // dies past the edge in the same tick, so it doesn't get away
+        hit(&mut test, ent, 100);
+        test.ticks(1);
+        assert!(test.get::<Enemy>(ent).is_none());
+        assert_eq!(
+            reader
+                .iter(test.resource::<Events<GoldEscapedEvent>>())
+                .count(),
+            0
+        );
+        assert_eq!(test.resource::<Lives>().count, Lives::new().count);
+        assert!(test.get::<Gold>(gold).is_some(), "gold wasn't dropped");
+    }
+
+    #[test]
+    fn hits_show_how_much_they_did() {
+        let mut test = TestApp::new(1);
+        let ent = test.spawn_enemy(START, GRUNT);
+        hit(&mut test, ent, 3);
+
+        let text = test
+            .app
+            .world
+            .query_filtered::<&Text, With<DamageNumber>>()
+            .single(&test.app.world);
+        assert_eq!(text.sections[0].value, "3");
+
+        test.seconds(DAMAGE_NUMBER_TIME + 0.1);
+        assert_eq!(test.count::<DamageNumber>(), 0);
+    }
+
+    #[test]
+    fn enemies_are_built_from_their_kind() {
+        let mut test = TestApp::new(1);
+        let ent = test.spawn_enemy(START, "tank");
+        let tank = EnemyTypes::default().get("tank").unwrap().clone();
+
+        let enemy = test.get::<Enemy>(ent).unwrap();
+        assert_eq!(enemy.speed, tank.speed);
+        assert_eq!(enemy.size, tank.size);
+        assert_eq!(test.get::<Health>(ent).unwrap().max, tank.health);
+        assert_eq!(test.get::<Armor>(ent).unwrap().0, tank.armor);
+    }
+
+    #[test]
+    fn splitters_break_apart_when_they_die() {
+        let mut test = TestApp::new(1);
+        let ent = test.spawn_enemy(START, "splitter");
+        hit(&mut test, ent, 100);
+        // cleaned up, then the pieces spawn
+        test.ticks(2);
+
+        let kinds: Vec<String> = test
+            .app
+            .world
+            .query::<&Enemy>()
+            .iter(&test.app.world)
+            .map(|e| e.kind.clone())
+            .collect();
+        assert_eq!(kinds, vec!["splitling"; 3]);
+    }
+
+    #[test]
+    fn hits_can_put_a_status_on() {
+        let mut test = TestApp::new(1);
+        let ent = test.spawn_enemy(START, GRUNT);
+        test.app.world.entity_mut(ent).insert(Health::new(100));
+        test.send(DamageEvent {
+            target: ent,
+            amount: 10,
+            status: Some(StatusHit {
+                status: Status::Mark(2.0),
+                seconds: 5.0,
+            }),
+        });
+        test.ticks(1);
+        // the mark goes on after the hit that brought it
+        assert_eq!(test.get::<Health>(ent).unwrap().current, 90);
+        hit(&mut test, ent, 10);
+        assert_eq!(test.get::<Health>(ent).unwrap().current, 70);
+
+        test.send(DamageEvent {
+            target: ent,
+            amount: 1,
+            status: Some(StatusHit {
+                status: Status::Stun,
+                seconds: 1.0,
+            }),
+        });
+        // drawn a tick behind, and the stun only lands after that tick's move
+        test.ticks(2);
+        let stunned = test.get::<Transform>(ent).unwrap().translation;
+        test.seconds(0.5);
+        assert_eq!(test.get::<Transform>(ent).unwrap().translation, stunned);
+    }
+
+    #[test]
+    fn stunned_enemies_are_not_pushed_around() {
+        let mut test = TestApp::new(1);
+        // close enough that they push each other apart
+        let a = test.spawn_enemy(START, GRUNT);
+        let b = test.spawn_enemy(START + Vec2::new(3.0, 0.0), GRUNT);
+        for target in [a, b] {
+            test.send(DamageEvent {
+                target,
+                amount: 1,
+                status: Some(StatusHit {
+                    status: Status::Stun,
+                    seconds: 1.0,
+                }),
+            });
+        }
+        // drawn a tick behind, and the stun only lands after that tick's move
+        test.ticks(2);
+        let at = |test: &TestApp, ent| test.get::<Transform>(ent).unwrap().translation;
+        let (a_at, b_at) = (at(&test, a), at(&test, b));
+        assert!(a_at.distance(b_at) < 10.0, "too far apart to push");
+
+        test.seconds(0.5);
+        assert_eq!(at(&test, a), a_at);
+        assert_eq!(at(&test, b), b_at);
+    }
+
+    fn fill_boss(test: &mut TestApp, ent: Entity, count: u32) {
+        test.app.world.get_mut::<GoldPile>(ent).unwrap().count = count;
+    }
+
+    #[test]
+    fn the_boss_walks_in_and_stops_short() {
+        let mut test = TestApp::new(1);
+        let ent = test.spawn_boss();
+        let start = test.get::<Transform>(ent).unwrap().translation.length();
+        test.seconds(2.0);
+        assert!(test.get::<Transform>(ent).unwrap().translation.length() < start);
+
+        test.app
+            .world
+            .get_mut::<Transform>(ent)
+            .unwrap()
+            .translation = Vec3::new(BOSS_STOP_DISTANCE + 1.0, 0.0, 0.2);
+        test.seconds(1.0);
+        let distance = test.get::<Transform>(ent).unwrap().translation.length();
+        assert!((distance - BOSS_STOP_DISTANCE).abs() < 0.01);
+    }
+
+    #[test]
+    fn a_quarter_full_boss_calls_minions() {
+        let mut test = TestApp::new(1);
+        let ent = test.spawn_boss();
+        fill_boss(&mut test, ent, BOSS_HEALTH / 4 + 1);
+        test.ticks(1);
+
+        assert_eq!(test.get::<Boss>(ent).unwrap().phase, 1);
+        assert_eq!(test.count::<Enemy>(), BOSS_MINIONS.len());
+    }
+
+    #[test]
+    fn a_full_boss_goes_down_before_the_win() {
+        let mut test = TestApp::new(1);
+        let ent = test.spawn_boss();
+        let mut reader = test.resource::<Events<BossCapEvent>>().get_reader();
+        fill_boss(&mut test, ent, BOSS_HEALTH);
+        test.ticks(2);
+        assert!(test.get::<Boss>(ent).unwrap().defeat.is_some());
+        assert_eq!(
+            reader.iter(test.resource::<Events<BossCapEvent>>()).count(),
+            0
+        );
+
+        let mut ticks = 0;
+        while test.get::<Boss>(ent).is_some() && ticks < 600 {
+            test.ticks(1);
+            ticks += 1;
+        }
+        assert!(test.get::<Boss>(ent).is_none(), "never went down");
+        assert!(ticks as f32 * TICK.as_secs_f32() >= BOSS_DEFEAT_TIME - 0.1);
+        assert_eq!(
+            reader.iter(test.resource::<Events<BossCapEvent>>()).count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::palette::CRIMSON;
+
+/// The basic enemy
+pub const GRUNT: &str = "grunt";
+
+/// Every kind of enemy, read from assets/enemies.ron
+///
+/// Built into the game rather than loaded as an asset,
+/// so the headless sim and tests get the same enemies.
+#[derive(Deserialize, Clone, Debug)]
+pub struct EnemyTypes {
+    enemies: Vec<EnemyType>,
+}
+
+/// One kind of enemy
+#[derive(Deserialize, Clone, Debug)]
+pub struct EnemyType {
+    pub id: String,
+    pub speed: f32,
+    pub health: u32,
+    #[serde(default)]
+    pub armor: u32,
+    // width and height
+    pub size: f32,
+    // hex, like "a12536"
+    pub color: String,
+    // gold it takes from a pile at once
+    #[serde(default = "one")]
+    pub carry: u32,
+    #[serde(default)]
+    pub behaviour: Vec<Behaviour>,
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub enum Behaviour {
+    // straight at the gold, through towers and all
+    Direct,
+    // breaks into smaller enemies when it dies
+    Split { into: String, count: u32 },
+}
+
+impl EnemyTypes {
+    pub fn from_ron(text: &str) -> Result<Self, ron::Error> {
+        ron::from_str(text)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&EnemyType> {
+        self.enemies.iter().find(|t| t.id == id)
+    }
+}
+
+impl Default for EnemyTypes {
+    fn default() -> Self {
+        EnemyTypes::from_ron(include_str!("../assets/enemies.ron")).expect("bad enemies.ron")
+    }
+}
+
+impl EnemyType {
+    pub fn color(&self) -> Color {
+        Color::hex(&self.color).unwrap_or(CRIMSON)
+    }
+
+    pub fn has(&self, behaviour: &Behaviour) -> bool {
+        self.behaviour.contains(behaviour)
+    }
+
+    /// What it breaks into, and how many
+    pub fn split(&self) -> Option<(&str, u32)> {
+        self.behaviour.iter().find_map(|b| match b {
+            Behaviour::Split { into, count } => Some((into.as_str(), *count)),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_enemies_make_sense() {
+        let types = EnemyTypes::default();
+        assert!(types.get(GRUNT).is_some());
+        for t in types.enemies.iter() {
+            assert!(Color::hex(&t.color).is_ok(), "{} has a bad color", t.id);
+            assert_eq!(types.enemies.iter().filter(|o| o.id == t.id).count(), 1);
+            if let Some((into, count)) = t.split() {
+                assert!(types.get(into).is_some(), "{} splits into nothing", t.id);
+                assert!(count > 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use bevy::{prelude::*, sprite::Mesh2dHandle};
+use std::collections::HashMap;
+
+use crate::hex::setup_hex_assets;
+use crate::palette::ORANGE;
+use crate::terrain::Terrain;
+use crate::tower::EXPLOSION_RADIUS;
+
+pub struct GameAssetsPlugin;
+
+impl Plugin for GameAssetsPlugin {
+    fn build(&self, app: &mut App) {
+        // before Startup so the UI built there can use them
+        app.add_startup_system_to_stage(StartupStage::PreStartup, load_game_assets)
+            .add_startup_system_to_stage(StartupStage::PreStartup, setup_hex_assets);
+    }
+}
+
+/// Every texture, font and mesh the game spawns with
+///
+/// Game logic only ever clones handles out of here, so it runs
+/// without an asset server. The headless simulation leaves them all empty.
+#[derive(Default)]
+pub struct GameAssets {
+    pub font: Handle<Font>,
+    pub gold: Handle<Image>,
+    pub bomb: Handle<Image>,
+    pub missile: Handle<Image>,
+    pub monster: Handle<Image>,
+    pub tower: Handle<Image>,
+    pub unbuilt_tower: Handle<Image>,
+    pub explosion_mesh: Mesh2dHandle,
+    pub explosion_material: Handle<ColorMaterial>,
+    terrain: HashMap<Terrain, Handle<Image>>,
+}
+
+impl GameAssets {
+    /// The sprite drawn on top of a hex
+    pub fn terrain(&self, terrain: Terrain) -> Handle<Image> {
+        self.terrain.get(&terrain).cloned().unwrap_or_default()
+    }
+}
+
+fn load_game_assets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(GameAssets {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        gold: asset_server.load("sprites/Gold2.png"),
+        bomb: asset_server.load("sprites/Gold1.png"),
+        missile: asset_server.load("sprites/Missile.png"),
+        monster: asset_server.load("sprites/Monster.png"),
+        tower: asset_server.load("sprites/Tower.png"),
+        unbuilt_tower: asset_server.load("sprites/UnbuiltTower.png"),
+        explosion_mesh: meshes
+            .add(shape::Circle::new(EXPLOSION_RADIUS).into())
+            .into(),
+        explosion_material: materials.add(ColorMaterial::from(ORANGE)),
+        terrain: Terrain::ALL
+            .iter()
+            .map(|&t| (t, asset_server.load(t.sprite())))
+            .collect(),
+    });
+}
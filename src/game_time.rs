@@ -0,0 +1,476 @@
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::game_assets::GameAssets;
+use crate::GameState;
+
+// the speeds the buttons can pick
+const SPEEDS: [f32; 4] = [0.5, 1.0, 2.0, 4.0];
+
+/// Gameplay moves on in steps of exactly this long
+pub const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+// a slow frame catches up this many ticks at most
+// past that the game slows down instead of freezing up
+const MAX_TICKS_PER_FRAME: u32 = 10;
+
+const NORMAL_SPEED_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_SPEED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
+const ACTIVE_SPEED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+
+pub struct GameTimePlugin;
+
+impl Plugin for GameTimePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(GameTime::new())
+            .add_stage_before(
+                CoreStage::Update,
+                GameTick,
+                SystemStage::single_threaded().with_run_criteria(run_ticks),
+            )
+            .add_system_to_stage(GameTick, begin_tick.exclusive_system().at_start())
+            .add_system(interpolate)
+            .add_startup_system(speed_buttons)
+            .add_system(speed_button_system)
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(reset_game_time));
+    }
+}
+
+/// The stage gameplay runs in
+///
+/// Runs once per TICK of game time while Playing. That can be
+/// several times in one frame, or not at all.
+/// State run criteria only work in Update, so nothing in here uses them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+pub struct GameTick;
+
+/// The order gameplay runs in within a tick
+///
+/// Unordered systems run in a different order every launch,
+/// so anything that touches what another system touches has to be
+/// in a different step, or ordered by hand. Otherwise replays drift.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemLabel)]
+pub enum TickStep {
+    // clear away what died last tick
+    Cleanup,
+    Timers,
+    Steer,
+    Move,
+    Hit,
+    // enemies getting away with gold
+    Escape,
+    // after everything has happened
+    Count,
+}
+
+impl TickStep {
+    /// A set that runs in this step, after the one before it
+    pub fn set(self) -> SystemSet {
+        let set = SystemSet::new().label(self);
+        match self.previous() {
+            Some(previous) => set.after(previous),
+            None => set,
+        }
+    }
+
+    fn previous(self) -> Option<TickStep> {
+        match self {
+            TickStep::Cleanup => None,
+            TickStep::Timers => Some(TickStep::Cleanup),
+            TickStep::Steer => Some(TickStep::Timers),
+            TickStep::Move => Some(TickStep::Steer),
+            TickStep::Hit => Some(TickStep::Move),
+            TickStep::Escape => Some(TickStep::Hit),
+            TickStep::Count => Some(TickStep::Escape),
+        }
+    }
+}
+
+/// Time as the game sees it
+///
+/// Gameplay timers and movement tick from this instead of Time
+/// so the speed buttons change everything at once.
+/// Only means anything in the GameTick stage.
+pub struct GameTime {
+    pub speed: f32,
+    // game time not yet run as a tick
+    accumulated: Duration,
+    ticks: u64,
+    // this frame so far. None if the game wasn't playing
+    frame: Option<Frame>,
+    // ticks a replay wants run this frame, instead of the clock's
+    script: Option<u32>,
+}
+
+/// How much gameplay ran in one frame
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    pub ticks: u32,
+    pub speed: f32,
+}
+
+impl GameTime {
+    pub fn new() -> Self {
+        GameTime {
+            speed: 1.0,
+            accumulated: Duration::ZERO,
+            ticks: 0,
+            frame: None,
+            script: None,
+        }
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn frame(&self) -> Option<Frame> {
+        self.frame
+    }
+
+    /// Runs this frame like a recorded one, whatever the clock says
+    ///
+    /// Has to be called before the GameTick stage.
+    pub fn play_frame(&mut self, frame: Frame) {
+        self.speed = frame.speed;
+        self.script = Some(frame.ticks);
+    }
+
+    pub fn delta(&self) -> Duration {
+        TICK
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        TICK.as_secs_f32()
+    }
+
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.ticks as f32 * TICK.as_secs_f32()
+    }
+
+    // how far through the next tick we are, 0 to 1
+    fn overstep(&self) -> f32 {
+        self.accumulated.as_secs_f32() / TICK.as_secs_f32()
+    }
+}
+
+// like FixedTimestep, but at the game's speed and only while playing
+fn run_ticks(
+    time: Res<Time>,
+    state: Res<State<GameState>>,
+    mut game_time: ResMut<GameTime>,
+    mut looping: Local<bool>,
+) -> ShouldRun {
+    // paused keeps the leftover so the picture doesn't jump
+    if *state.current() != GameState::Playing {
+        *looping = false;
+        game_time.frame = None;
+        game_time.script = None;
+        return ShouldRun::No;
+    }
+
+    // first check this frame
+    if !*looping {
+        game_time.frame = Some(Frame {
+            ticks: 0,
+            speed: game_time.speed,
+        });
+        // a replay's frames leave the clock alone
+        if game_time.script.is_none() {
+            // f32 loses whole nanoseconds, and then ticks
+            let speed = game_time.speed as f64;
+            game_time.accumulated += time.delta().mul_f64(speed);
+            game_time.accumulated = game_time.accumulated.min(TICK * MAX_TICKS_PER_FRAME);
+        }
+    }
+
+    let tick = match game_time.script {
+        Some(left) => {
+            game_time.script = left.checked_sub(1);
+            left > 0
+        }
+        None if game_time.accumulated >= TICK => {
+            game_time.accumulated -= TICK;
+            true
+        }
+        None => false,
+    };
+
+    if tick {
+        game_time.ticks += 1;
+        if let Some(frame) = &mut game_time.frame {
+            frame.ticks += 1;
+        }
+        *looping = true;
+        ShouldRun::YesAndCheckAgain
+    } else {
+        *looping = false;
+        ShouldRun::No
+    }
+}
+
+fn reset_game_time(mut game_time: ResMut<GameTime>) {
+    *game_time = GameTime::new();
+}
+
+/// Drawn partway between its last two ticks
+///
+/// Without it movement stutters whenever frames and ticks don't line up.
+#[derive(Component)]
+pub struct Interpolated {
+    previous: Vec3,
+    current: Vec3,
+    // what interpolate last wrote to the transform
+    drawn: Option<Vec3>,
+}
+
+impl Interpolated {
+    pub fn new(translation: Vec3) -> Self {
+        Interpolated {
+            previous: translation,
+            current: translation,
+            drawn: None,
+        }
+    }
+}
+
+// put back where gameplay left things before it moves them again
+fn begin_tick(world: &mut World) {
+    let mut q_interpolated = world.query::<(&mut Transform, &mut Interpolated)>();
+    for (mut trans, mut interp) in q_interpolated.iter_mut(world) {
+        if let Some(drawn) = interp.drawn.take() {
+            // otherwise something moved it outside the tick. Keep that
+            if trans.translation == drawn {
+                trans.translation = interp.current;
+            }
+        }
+        interp.previous = trans.translation;
+    }
+}
+
+pub fn interpolate(
+    game_time: Res<GameTime>,
+    mut q_interpolated: Query<(&mut Transform, &mut Interpolated)>,
+) {
+    let t = game_time.overstep();
+    for (mut trans, mut interp) in q_interpolated.iter_mut() {
+        match interp.drawn {
+            // ticked since the last draw
+            None => interp.current = trans.translation,
+            // no tick, still where it was drawn
+            Some(drawn) if drawn == trans.translation => {}
+            // moved outside the tick. Jump there
+            Some(_) => {
+                interp.previous = trans.translation;
+                interp.current = trans.translation;
+            }
+        }
+        trans.translation = interp.previous.lerp(interp.current, t);
+        interp.drawn = Some(trans.translation);
+    }
+}
+
+#[derive(Component)]
+struct SpeedButton(f32);
+
+fn speed_buttons(mut commands: Commands, game_assets: Res<GameAssets>) {
+    let font = game_assets.font.clone();
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(5.0),
+                    left: Val::Px(15.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Row,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .with_children(|row| {
+            for speed in SPEEDS {
+                row.spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(60.0), Val::Px(35.0)),
+                        margin: UiRect::all(Val::Px(3.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    color: NORMAL_SPEED_BUTTON.into(),
+                    ..default()
+                })
+                .insert(SpeedButton(speed))
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section(
+                        format!("{}x", speed),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 25.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    ));
+                });
+            }
+        });
+}
+
+fn speed_button_system(
+    mut game_time: ResMut<GameTime>,
+    mut q_buttons: Query<(&Interaction, &SpeedButton, &mut UiColor)>,
+) {
+    for (interaction, button, _) in q_buttons.iter() {
+        if *interaction == Interaction::Clicked {
+            game_time.speed = button.0;
+        }
+    }
+
+    // the button for the current speed stays lit
+    for (interaction, button, mut color) in q_buttons.iter_mut() {
+        *color = if button.0 == game_time.speed {
+            ACTIVE_SPEED_BUTTON.into()
+        } else if *interaction == Interaction::Hovered {
+            HOVERED_SPEED_BUTTON.into()
+        } else {
+            NORMAL_SPEED_BUTTON.into()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::time::create_time_channels;
+    use bevy::utils::Instant;
+
+    #[derive(Default)]
+    struct Ran(u32);
+
+    fn count(mut ran: ResMut<Ran>) {
+        ran.0 += 1;
+    }
+
+    // frames of the given length, with the tick stage counting its runs
+    fn run_frames(state: GameState, speed: f32, frame: Duration, frames: u32) -> (u32, u64) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_state(state)
+            .insert_resource(GameTime {
+                speed,
+                ..GameTime::new()
+            })
+            .init_resource::<Ran>()
+            .add_stage_before(
+                CoreStage::Update,
+                GameTick,
+                SystemStage::single_threaded().with_run_criteria(run_ticks),
+            )
+            .add_system_to_stage(GameTick, count);
+        let (sender, receiver) = create_time_channels();
+        app.insert_resource(receiver);
+
+        let start = Instant::now();
+        // the first frame only starts the clock
+        for i in 0..=frames {
+            let _ = sender.0.try_send(start + frame * i);
+            app.update();
+        }
+        (
+            app.world.resource::<Ran>().0,
+            app.world.resource::<GameTime>().ticks,
+        )
+    }
+
+    #[test]
+    fn ticks_whatever_the_frame_rate() {
+        // a second of frames, fast or slow
+        assert_eq!(run_frames(GameState::Playing, 1.0, TICK / 2, 120), (60, 60));
+        assert_eq!(run_frames(GameState::Playing, 1.0, TICK * 3, 20), (60, 60));
+        // double speed is twice the ticks
+        assert_eq!(run_frames(GameState::Playing, 2.0, TICK, 60), (120, 120));
+        // a long hitch only catches up so far
+        assert_eq!(
+            run_frames(GameState::Playing, 1.0, TICK * 100, 1),
+            (MAX_TICKS_PER_FRAME, MAX_TICKS_PER_FRAME as u64)
+        );
+    }
+
+    #[test]
+    fn only_ticks_while_playing() {
+        assert_eq!(run_frames(GameState::Menu, 1.0, TICK, 60), (0, 0));
+        assert_eq!(run_frames(GameState::Paused, 1.0, TICK, 60), (0, 0));
+    }
+
+    #[test]
+    fn replays_recorded_frames() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_state(GameState::Playing)
+            .insert_resource(GameTime::new())
+            .init_resource::<Ran>()
+            .add_stage_before(
+                CoreStage::Update,
+                GameTick,
+                SystemStage::single_threaded().with_run_criteria(run_ticks),
+            )
+            .add_system_to_stage(GameTick, count);
+        let (sender, receiver) = create_time_channels();
+        app.insert_resource(receiver);
+        let start = Instant::now();
+
+        // however long the frames really take
+        let frames = [(3, 2.0), (0, 2.0), (1, 0.5)];
+        for (i, (ticks, speed)) in frames.into_iter().enumerate() {
+            let frame = Frame { ticks, speed };
+            let _ = sender.0.try_send(start + TICK * 50 * i as u32);
+            app.world.resource_mut::<GameTime>().play_frame(frame);
+            app.update();
+            assert_eq!(app.world.resource::<GameTime>().frame(), Some(frame));
+        }
+        assert_eq!(app.world.resource::<Ran>().0, 4);
+
+        // and back to the clock after, at the last frame's speed
+        let _ = sender.0.try_send(start + TICK * 102);
+        app.update();
+        assert_eq!(app.world.resource::<GameTime>().frame().unwrap().ticks, 1);
+    }
+
+    #[test]
+    fn draws_between_ticks() {
+        let mut world = World::new();
+        world.insert_resource(GameTime {
+            accumulated: TICK / 4,
+            ..GameTime::new()
+        });
+        let moved = world
+            .spawn()
+            .insert(Transform::from_xyz(0.0, 0.0, 0.0))
+            .insert(Interpolated::new(Vec3::ZERO))
+            .id();
+        let mut stage = SystemStage::single_threaded().with_system(interpolate);
+
+        // a tick moves it
+        begin_tick(&mut world);
+        world.get_mut::<Transform>(moved).unwrap().translation = Vec3::new(8.0, 0.0, 0.0);
+        stage.run(&mut world);
+        let x = world.get::<Transform>(moved).unwrap().translation.x;
+        assert!((x - 2.0).abs() < 0.001);
+
+        // the next tick carries on from where gameplay left it
+        begin_tick(&mut world);
+        assert_eq!(world.get::<Transform>(moved).unwrap().translation.x, 8.0);
+
+        // moved outside a tick, it jumps straight there
+        stage.run(&mut world);
+        world.get_mut::<Transform>(moved).unwrap().translation = Vec3::new(50.0, 0.0, 0.0);
+        stage.run(&mut world);
+        assert_eq!(world.get::<Transform>(moved).unwrap().translation.x, 50.0);
+        begin_tick(&mut world);
+        assert_eq!(world.get::<Transform>(moved).unwrap().translation.x, 50.0);
+    }
+}
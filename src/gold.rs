@@ -4,11 +4,15 @@ use bevy::sprite::Anchor;
 use bevy::utils::Duration;
 
 use crate::boids::Boid;
-use crate::enemies::{Boss, BossCapEvent, Dead, Enemy};
-use crate::hex::{Hex, HexCollection, HexCoords, Selection, DEG_TO_RAD};
+use crate::enemies::{Boss, Dead, Enemy, BOSS_PHASES};
+use crate::game_assets::GameAssets;
+use crate::game_time::{GameTick, GameTime, Interpolated, TickStep};
+use crate::hex::{gather_gold, Blocked, Hex, HexCollection, HexCoords, Selection, DEG_TO_RAD};
+use crate::lives::{GameOverEvent, GameOverReason};
+use crate::replay::{apply_commands, PlayerCommand, PlayerInput};
 use crate::tower::{Tower, TowerPreview};
-use crate::MouseWorldPos;
 use crate::{palette::*, tower};
+use crate::{GameState, MouseWorldPos};
 
 const GOLD_SPAWN_TIME: f32 = 10.0;
 
@@ -23,34 +27,60 @@ impl Plugin for GoldPlugin {
             .add_event::<SpawnGoldEvent>()
             .add_event::<DelayedGoldEvent>()
             .add_event::<DelayedGoldEventHelper>()
-            .add_startup_system(setup)
-            .add_system(pile_input)
-            .add_system(spawn_pile)
-            .add_system(remove_pile)
-            .add_system(generate_gold)
-            .add_system(spawn_gold)
-            .add_system(delay_gold)
-            .add_system(delay_gold_helper)
+            .add_system(spawn_pile.after(apply_commands))
+            .add_system(
+                remove_pile
+                    .after(apply_commands)
+                    .after(tower::preview_paid_for)
+                    .before(tower::remove_tower),
+            )
+            // after everything that drops gold, in the same order every time
+            .add_system(
+                spawn_gold
+                    .after(remove_pile)
+                    .after(tower::remove_tower)
+                    .after(delay_gold_helper),
+            )
             //.add_system(place_spawner)
             //.add_system(remove_spawner)
             //.add_system(check_spawner)
-            .add_system(move_gold)
-            .add_system(check_mouse)
-            //.add_system(store_gold.before(enemies::grab_gold))
-            // enemy.bullet_hit might break this. It was before enemy::grab
-            // so I'm putting it before this
-            .add_system(gold_collisions.before(tower::bullet_hit))
-            // spawn a health bar before it gets destroyed
-            // if you are holding gold when you spawna tower, it gets built instantly
-            // and the health bar is removed before it is created
-            .add_system(make_health_bar.before(gold_collisions))
-            .add_system(animate_health_bar);
+            // piles are only taken away in Update, after the tick fills them
+            // so a pile always gets its health bar before it goes
+            .add_system(make_health_bar)
+            .add_system(animate_health_bar)
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(clear_gold))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(pile_input.label(PlayerInput))
+                    .with_system(delay_gold)
+                    .with_system(delay_gold_helper.after(delay_gold)),
+            )
+            // mines what the hexes gathered this tick
+            .add_system_set_to_stage(
+                GameTick,
+                TickStep::Timers
+                    .set()
+                    .with_system(generate_gold.after(gather_gold)),
+            )
+            .add_system_set_to_stage(GameTick, TickStep::Steer.set().with_system(check_mouse))
+            .add_system_set_to_stage(GameTick, TickStep::Move.set().with_system(move_gold))
+            .add_system_set_to_stage(
+                GameTick,
+                TickStep::Hit
+                    .set()
+                    //.add_system(store_gold.before(enemies::grab_gold))
+                    // enemy.bullet_hit might break this. It was before enemy::grab
+                    // so I'm putting it before this
+                    .with_system(gold_collisions.before(tower::bullet_hit))
+                    // both hand out gold to enemies
+                    .with_system(steal_gold.after(gold_collisions).before(tower::bullet_hit)),
+            );
     }
 }
 
 #[derive(Component)]
 pub struct GoldSpawner {
-    timer: Timer,
+    pub timer: Timer,
     pub radius: u32,
     //gold_gen: u32,
 }
@@ -70,6 +100,7 @@ pub struct SpawnGoldEvent {
 }
 
 pub struct ModifySpawnerEvent {
+    #[allow(dead_code)]
     pub coords: HexCoords,
     //pub modification: Modification,
 }
@@ -119,6 +150,13 @@ impl PileSpawnEvent {
             starting_gold: 0,
         }
     }
+
+    pub fn with_gold(coords: HexCoords, starting_gold: u32) -> Self {
+        PileSpawnEvent {
+            coords,
+            starting_gold,
+        }
+    }
 }
 
 pub struct PileCapEvent {
@@ -130,14 +168,6 @@ pub struct PileRemoveEvent {
     pub coords: HexCoords,
 }
 
-fn setup(mut ev_spawn: EventWriter<PileSpawnEvent>) {
-    // spawn a pile at the center with some starting cash
-    ev_spawn.send(PileSpawnEvent {
-        coords: HexCoords::new(),
-        starting_gold: 11,
-    });
-}
-
 fn gold_collisions(
     mut commands: Commands,
     mut q_gold: Query<(Entity, &mut Transform), (Without<Enemy>, With<Gold>)>,
@@ -155,7 +185,6 @@ fn gold_collisions(
         (Without<Gold>, Without<Dead>, Without<Boss>),
     >,
     mut ev_cap: EventWriter<PileCapEvent>,
-    mut ev_boss_cap: EventWriter<BossCapEvent>,
 ) {
     for (gold_ent, mut gold_trans) in q_gold.iter_mut() {
         let mut gold_alive = true;
@@ -180,6 +209,7 @@ fn gold_collisions(
                 pile.count += 1;
                 //println!("Plink! {:?} e: {:?}", pile.count, gold_ent);
                 commands.entity(gold_ent).despawn_recursive();
+                // a full boss is beaten by enemies::boss_phases
                 if pile.count == pile.gold_cap {
                     //println!("Cap reached! {:?}", pile.count);
                     if let Some(hex) = hex {
@@ -191,8 +221,6 @@ fn gold_collisions(
                             pile.count = 0; // empty the pile to pay for tower
                             pile.gold_cap = 0; // don't accept any more
                         }
-                    } else {
-                        ev_boss_cap.send(BossCapEvent);
                     }
                 }
                 gold_alive = false;
@@ -219,14 +247,18 @@ fn gold_collisions(
                     gold_trans.translation,
                     Vec2::new(8., 12.),
                     e_trans.translation,
-                    Vec2::new(15., 15.),
+                    Vec2::splat(enemy.size),
                 )
                 .is_some()
                 {
                     //println!("Grabbed a gold: ent: {:?}", gold_ent);
                     enemy.has_gold = true;
 
-                    commands.entity(gold_ent).remove::<Gold>();
+                    // rides along with the enemy from here
+                    commands
+                        .entity(gold_ent)
+                        .remove::<Gold>()
+                        .remove::<Interpolated>();
 
                     commands.entity(e_ent).add_child(gold_ent);
                     gold_trans.translation = Vec3::new(0.0, 0.0, 0.1);
@@ -237,6 +269,74 @@ fn gold_collisions(
     }
 }
 
+// enemies standing on a pile take a gold from it
+// emptying the center pile loses the game
+fn steal_gold(
+    mut commands: Commands,
+    mut q_enemies: Query<
+        (Entity, &Transform, &mut Enemy),
+        (Without<Gold>, Without<Dead>, Without<Boss>),
+    >,
+    mut q_piles: Query<(&Hex, &mut GoldPile)>,
+    hex_collect: Res<HexCollection>,
+    game_assets: Res<GameAssets>,
+    mut ev_game_over: EventWriter<GameOverEvent>,
+) {
+    for (e_ent, e_trans, mut enemy) in q_enemies.iter_mut() {
+        if enemy.has_gold {
+            continue;
+        }
+
+        let coords = HexCoords::from_position(e_trans.translation.truncate());
+        if let Some(&e) = hex_collect.hexes.get(&coords) {
+            if let Ok((hex, mut pile)) = q_piles.get_mut(e) {
+                if pile.count == 0 {
+                    continue;
+                }
+                // as much as it can carry
+                let taken = enemy.carry.min(pile.count);
+                pile.count -= taken;
+                enemy.has_gold = true;
+
+                for _ in 0..taken {
+                    carry_gold(&mut commands, e_ent, &game_assets);
+                }
+
+                if pile.count == 0 && hex.coords == HexCoords::new() {
+                    ev_game_over.send(GameOverEvent {
+                        reason: GameOverReason::TreasuryDrained,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Gives an enemy a gold to run off with
+/// Like gold picked up off the ground
+pub fn carry_gold(commands: &mut Commands, enemy: Entity, game_assets: &GameAssets) {
+    let gold = commands
+        .spawn_bundle(SpriteBundle {
+            texture: game_assets.gold.clone(),
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, 0.1),
+                scale: Vec3::ONE * 2.0,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Boid::new())
+        .id();
+    commands.entity(enemy).add_child(gold);
+}
+
+// back to the menu. Get rid of everything from the last game
+fn clear_gold(mut commands: Commands, q_gold: Query<Entity, With<Gold>>) {
+    for ent in q_gold.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+}
+
 #[derive(Component)]
 struct PileSprite;
 
@@ -247,57 +347,69 @@ fn spawn_pile(
         Entity,
         (
             With<Hex>,
-            (Without<TowerPreview>, Without<Tower>, Without<GoldPile>),
+            (
+                Without<TowerPreview>,
+                Without<Tower>,
+                Without<GoldPile>,
+                Without<Blocked>,
+            ),
         ),
     >,
     hex_collect: Res<HexCollection>,
 ) {
     // don't run before hexes exist
     // this preserves the event that is send frame ~1
-    // until hexes exist on frame ~2
-    // then on frame ~3 this runs
-    // or maybe frame ~2 if this system happens to run after the hex spawn system
+    // until hexes exist on frame ~2, when this runs.
+    // events only last two frames, so whatever sends the board
+    // has to go before spawn_hex or the piles are lost
     if !q_hexes.is_empty() {
         for ev in ev_spawn.iter() {
             if let Some(&e) = hex_collect.hexes.get(&ev.coords) {
                 if let Ok(ent) = q_hexes.get(e) {
                     // for (ent, hex) in q_hexes.iter() {
                     //     if ev.coords == (hex.coords) {
-                    commands
-                        .entity(ent)
-                        .insert(GoldPile {
+                    insert_pile(
+                        &mut commands,
+                        ent,
+                        GoldPile {
                             count: ev.starting_gold,
                             gold_cap: 500,
-                        })
-                        .with_children(|parent| {
-                            parent
-                                .spawn_bundle(SpriteBundle {
-                                    sprite: Sprite {
-                                        color: ORANGE,
-                                        custom_size: Some(Vec2::new(20.0, 20.0)),
-                                        ..default()
-                                    },
-                                    transform: Transform {
-                                        // spawn on top of the underlying hex
-                                        translation: Vec3 {
-                                            x: 0.0,
-                                            y: 0.0,
-                                            z: 0.2,
-                                        },
-                                        // undo the hex's rotation
-                                        rotation: Quat::from_rotation_z(-30.0 * DEG_TO_RAD),
-                                        ..default()
-                                    },
-                                    ..default()
-                                })
-                                .insert(PileSprite);
-                        });
+                        },
+                    );
                 }
             }
         }
     }
 }
 
+/// Puts a pile on a hex
+/// Also used when loading a save
+pub fn insert_pile(commands: &mut Commands, ent: Entity, pile: GoldPile) {
+    commands.entity(ent).insert(pile).with_children(|parent| {
+        parent
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: ORANGE,
+                    custom_size: Some(Vec2::new(20.0, 20.0)),
+                    ..default()
+                },
+                transform: Transform {
+                    // spawn on top of the underlying hex
+                    translation: Vec3 {
+                        x: 0.0,
+                        y: 0.0,
+                        z: 0.2,
+                    },
+                    // undo the hex's rotation
+                    rotation: Quat::from_rotation_z(-30.0 * DEG_TO_RAD),
+                    ..default()
+                },
+                ..default()
+            })
+            .insert(PileSprite);
+    });
+}
+
 fn remove_pile(
     mut commands: Commands,
     mut ev_remove: EventReader<PileRemoveEvent>,
@@ -339,6 +451,8 @@ fn remove_pile(
 #[derive(Component)]
 struct HealthBar {
     is_background: bool,
+    // when the pile is full
+    width: f32,
 }
 
 fn make_health_bar(mut commands: Commands, q_new: Query<(Entity, Option<&Boss>), Added<GoldPile>>) {
@@ -346,22 +460,30 @@ fn make_health_bar(mut commands: Commands, q_new: Query<(Entity, Option<&Boss>),
         let mut r = Quat::from_rotation_z(-30.0 * DEG_TO_RAD);
         let mut y = 0.0;
         let mut x = -8.0;
+        let mut width = 25.0;
         if boss.is_some() {
             r = Quat::default();
             y = -42.0;
             x = 0.0;
+            width = 80.0;
         }
+        // the boss's bar fills from the left, so the phase notches line up
+        let (anchor, fill_x) = if boss.is_some() {
+            (Anchor::CenterLeft, x - width / 2.0)
+        } else {
+            (Anchor::Center, x)
+        };
         commands.entity(ent).with_children(|hex| {
             hex.spawn_bundle(SpriteBundle {
                 sprite: Sprite {
                     color: GOLD,
                     custom_size: Some(Vec2::new(0.0, 6.0)), // 25.0
-                    anchor: Anchor::Center,
+                    anchor,
                     ..default()
                 },
                 transform: Transform {
                     translation: Vec3 {
-                        x,
+                        x: fill_x,
                         y: -12.0 + y,
                         z: 0.5,
                     },
@@ -372,12 +494,13 @@ fn make_health_bar(mut commands: Commands, q_new: Query<(Entity, Option<&Boss>),
             })
             .insert(HealthBar {
                 is_background: false,
+                width,
             });
 
             hex.spawn_bundle(SpriteBundle {
                 sprite: Sprite {
                     color: DARK_BLUE,
-                    custom_size: Some(Vec2::new(27.0, 8.0)),
+                    custom_size: Some(Vec2::new(width + 2.0, 8.0)),
                     ..default()
                 },
                 transform: Transform {
@@ -393,7 +516,22 @@ fn make_health_bar(mut commands: Commands, q_new: Query<(Entity, Option<&Boss>),
             })
             .insert(HealthBar {
                 is_background: true,
+                width,
             });
+
+            if boss.is_some() {
+                for (at, _) in BOSS_PHASES {
+                    hex.spawn_bundle(SpriteBundle {
+                        sprite: Sprite {
+                            color: DARK_BLUE,
+                            custom_size: Some(Vec2::new(2.0, 8.0)),
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(fill_x + at * width, -12.0 + y, 0.6),
+                        ..default()
+                    });
+                }
+            }
         });
     }
 }
@@ -409,7 +547,7 @@ fn animate_health_bar(
             match pile {
                 Ok(p) => {
                     let x = p.count as f32 / p.gold_cap as f32;
-                    sprite.custom_size = Some(Vec2::new(x * 25.0, 6.0));
+                    sprite.custom_size = Some(Vec2::new(x * bar.width, 6.0));
                 }
                 Err(_) => {
                     //println!("Error. No pile");
@@ -422,16 +560,15 @@ fn animate_health_bar(
 
 fn pile_input(
     input: Res<Input<KeyCode>>,
-    mut ev_spawn: EventWriter<PileSpawnEvent>,
-    mut ev_remove: EventWriter<PileRemoveEvent>,
+    mut ev_command: EventWriter<PlayerCommand>,
     q_selection: Query<&Hex, With<Selection>>,
 ) {
     for hex in q_selection.iter() {
         if input.just_pressed(KeyCode::X) {
-            ev_remove.send(PileRemoveEvent { coords: hex.coords });
+            ev_command.send(PlayerCommand::RemovePile { coords: hex.coords });
         }
         if input.just_pressed(KeyCode::G) {
-            ev_spawn.send(PileSpawnEvent::new(hex.coords));
+            ev_command.send(PlayerCommand::SpawnPile { coords: hex.coords });
         }
     }
 }
@@ -440,19 +577,27 @@ fn generate_gold(
     mut q_gold_spawners: Query<(&Hex, &mut GoldSpawner)>,
     mut q_empty_hexes: Query<
         (&Transform, &mut Hex),
-        (Without<Tower>, Without<GoldPile>, Without<GoldSpawner>),
+        (
+            Without<Tower>,
+            Without<GoldPile>,
+            Without<GoldSpawner>,
+            Without<Blocked>,
+        ),
     >,
     mut ev_gold_spawn: EventWriter<SpawnGoldEvent>,
-    time: Res<Time>,
+    time: Res<GameTime>,
     hex_collect: Res<HexCollection>,
 ) {
     for (hex, mut spawner) in q_gold_spawners.iter_mut() {
         if spawner.timer.tick(time.delta()).just_finished() {
             // spawn around you
-            let mut neighbours = Vec::new();
-            for i in 1..=spawner.radius {
-                neighbours.append(&mut hex.coords.get_ring(i));
-            }
+            // skip the first. It's the spawner's own hex
+            let neighbours: Vec<HexCoords> = hex
+                .coords
+                .spiral(spawner.radius)
+                .into_iter()
+                .skip(1)
+                .collect();
             // let mut neighbours = hex.coords.get_ring(1);
             // let mut outer_ring = hex.coords.get_ring(2);
             // neighbours.append(&mut outer_ring);
@@ -471,8 +616,8 @@ fn generate_gold(
                     if let Ok((trans2, mut hex2)) = q_empty_hexes.get_mut(e) {
                         // empty space
 
-                        // mine and return success
-                        if hex2.mine() {
+                        // mine and spawn whatever came out
+                        for _ in 0..hex2.mine() {
                             ev_gold_spawn.send(SpawnGoldEvent {
                                 position: trans2.translation,
                                 //frame: (i*10)+1,
@@ -544,15 +689,15 @@ fn delay_gold_helper(
     }
 }
 
-fn spawn_gold(
+pub fn spawn_gold(
     mut commands: Commands,
     mut ev_gold_spawn: EventReader<SpawnGoldEvent>,
-    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
 ) {
     for ev in ev_gold_spawn.iter() {
         commands
             .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("sprites/Gold2.png"),
+                texture: game_assets.gold.clone(),
                 // sprite: Sprite {
                 //     color: GOLD,
                 //     custom_size: Some(Vec2::new(8.0, 12.)),
@@ -570,7 +715,8 @@ fn spawn_gold(
                 ..default()
             })
             .insert(Gold)
-            .insert(Boid::new());
+            .insert(Boid::new())
+            .insert(Interpolated::new(ev.position.truncate().extend(0.3)));
     }
 }
 
@@ -598,14 +744,97 @@ fn check_mouse(
     }
 }
 
+// follows the mouse as fast in real time whatever speed the game is at
+// more ticks a second at higher speeds, so each one moves it less
 fn move_gold(
     mut q_gold: Query<&mut Transform, (With<Gold>, With<MouseFollow>)>,
     mouse: Res<MouseWorldPos>,
-    time: Res<Time>,
+    time: Res<GameTime>,
 ) {
     for mut gold in q_gold.iter_mut() {
         let dir = mouse.0 - gold.translation.truncate();
         gold.translation +=
-            dir.normalize_or_zero().extend(0.0) * GOLD_MOVE_SPEED * time.delta_seconds();
+            dir.normalize_or_zero().extend(0.0) * GOLD_MOVE_SPEED * time.delta_seconds()
+                / time.speed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemy_types::GRUNT;
+    use crate::lives::Lives;
+    use crate::test_app::TestApp;
+
+    #[test]
+    fn enemies_steal_from_piles() {
+        let mut test = TestApp::new(1);
+        let coords = HexCoords::from((1, 0));
+        test.send(PileSpawnEvent::with_gold(coords, 3));
+        test.ticks(1);
+        let enemy = test.spawn_enemy(coords.to_position(), GRUNT);
+        test.ticks(1);
+
+        let hex = test.hex(coords);
+        assert_eq!(test.get::<GoldPile>(hex).unwrap().count, 2);
+        assert!(test.get::<Enemy>(enemy).unwrap().has_gold);
+
+        // one at a time
+        test.ticks(5);
+        assert_eq!(test.get::<GoldPile>(hex).unwrap().count, 2);
+
+        // and it gets away with it
+        let lives = test.resource::<Lives>().count;
+        test.seconds(9.0);
+        assert!(test.get::<Enemy>(enemy).is_none());
+        assert_eq!(test.resource::<Lives>().count, lives - 1);
+    }
+
+    #[test]
+    fn thieves_take_a_handful() {
+        let mut test = TestApp::new(1);
+        let coords = HexCoords::from((1, 0));
+        test.send(PileSpawnEvent::with_gold(coords, 5));
+        test.ticks(1);
+        let thief = test.spawn_enemy(coords.to_position(), "thief");
+        test.ticks(1);
+
+        let hex = test.hex(coords);
+        assert_eq!(test.get::<GoldPile>(hex).unwrap().count, 2);
+        assert_eq!(test.get::<Children>(thief).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn enemies_pick_up_loose_gold() {
+        let mut test = TestApp::new(1);
+        let coords = HexCoords::from((0, -1));
+        let enemy = test.spawn_enemy(coords.to_position(), GRUNT);
+        test.drop_gold(coords, 2);
+        test.ticks(2);
+
+        assert!(test.get::<Enemy>(enemy).unwrap().has_gold);
+        // the one it carries isn't loose any more. The other is left
+        assert_eq!(test.count::<Gold>(), 1);
+        let children = test.get::<Children>(enemy).unwrap();
+        assert!(!children.is_empty());
+    }
+
+    #[test]
+    fn draining_the_treasury_loses() {
+        let mut test = TestApp::new(1);
+        let center = HexCoords::new();
+        test.send(PileSpawnEvent::with_gold(center, 1));
+        test.ticks(1);
+        test.spawn_enemy(center.to_position(), GRUNT);
+        test.ticks(2);
+
+        assert_eq!(
+            *test.resource::<State<GameState>>().current(),
+            GameState::Lost
+        );
+        assert_eq!(
+            test.resource::<Lives>().lost_to,
+            Some(GameOverReason::TreasuryDrained)
+        );
     }
 }
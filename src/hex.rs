@@ -1,16 +1,22 @@
-use bevy::sprite::collide_aabb::collide;
 use bevy::ui::FocusPolicy;
-//use crate::GameState;
 use bevy::utils::Duration;
-use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
-use std::collections::HashMap;
+use bevy::{
+    prelude::*,
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 // what is std::hashmap
 // vs bevy utils hashmap?
 // are they the same?
 
+use crate::game_assets::GameAssets;
+use crate::game_time::{GameTick, GameTime, TickStep};
 use crate::gold::GoldPile;
+use crate::map::{HexDefaults, MapGrowth};
+use crate::terrain::Terrain;
 use crate::tower::Tower;
-use crate::MouseWorldPos;
+use crate::{GameState, MouseWorldPos};
 pub struct HexPlugin;
 
 impl Plugin for HexPlugin {
@@ -19,18 +25,24 @@ impl Plugin for HexPlugin {
             .insert_resource(HexCollection {
                 hexes: HashMap::new(),
             })
-            .add_startup_system(spawn_hexes_circle)
-            .add_system(spawn_ring_over_time)
+            .insert_resource(HexGrowth::none())
+            .insert_resource(HexSelection::default())
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(reset_board))
             .add_system(spawn_hex)
             .add_system(hex_intro)
-            .add_system(highlight_selection.before(select_hex))
+            // removed components are only seen by later stages
+            .add_system_to_stage(CoreStage::PostUpdate, highlight_selection)
             .add_system(select_hex)
-            .add_system(gather_gold)
             .add_system(info_panel)
             .add_system(remove_old_panel)
-            .add_system(test_from_pos);
-        // .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_hexes_circle))
-        // .add_system_set(SystemSet::on_update(GameState::Playing).with_system(spawn_hex));
+            .add_system(test_from_pos)
+            .add_system_set_to_stage(
+                GameTick,
+                TickStep::Timers
+                    .set()
+                    .with_system(spawn_ring_over_time)
+                    .with_system(gather_gold),
+            );
     }
 }
 
@@ -39,38 +51,54 @@ const HEX_SPACING: f32 = 0.866_025_4;
 const HEX_RADIUS: f32 = 27.0; // 20.0
 const HEX_MARGIN: f32 = 0.0; // 0.4
 
+#[derive(Clone)]
 pub struct HexSpawnEvent {
-    coords: HexCoords,
+    pub coords: HexCoords,
+    pub terrain: Terrain,
+    pub gold: u32,
+    pub max_gold: u32,
+    pub regen_time: f32,
+    // can't be built on or mined
+    pub blocked: bool,
 }
 
 #[derive(Component)]
 pub struct Hex {
-    radius: f32,
     pub coords: HexCoords,
+    pub terrain: Terrain,
     // gold available to be mined
     pub gold: u32,
-    max_gold: u32,
+    pub max_gold: u32,
     // when gold increments
-    timer: Timer,
+    pub timer: Timer,
 }
 
 impl Hex {
-    pub fn new(radius: f32, coords: HexCoords) -> Self {
+    pub fn new(
+        coords: HexCoords,
+        terrain: Terrain,
+        gold: u32,
+        max_gold: u32,
+        regen_time: f32,
+    ) -> Self {
         Hex {
-            radius,
             coords,
-            gold: 1,
-            max_gold: 3,
-            timer: Timer::from_seconds(7.5, true),
+            terrain,
+            gold,
+            max_gold,
+            timer: Timer::from_seconds(regen_time, true),
         }
     }
 
-    pub fn mine(&mut self) -> bool {
+    /// Takes gold out of the hex
+    /// Returns how much was taken. Always leaves 1 behind
+    pub fn mine(&mut self) -> u32 {
         if self.gold > 1 {
-            self.gold -= 1;
-            return true;
+            let amount = self.terrain.mining_yield().min(self.gold - 1);
+            self.gold -= amount;
+            return amount;
         }
-        false
+        0
     }
 }
 
@@ -78,26 +106,19 @@ pub struct HexCollection {
     pub hexes: HashMap<HexCoords, Entity>,
 }
 
-fn gather_gold(mut q_hexes: Query<&mut Hex>, time: Res<Time>) {
+// a hex that exists but can't be built on or mined
+#[derive(Component)]
+pub struct Blocked;
+
+pub fn gather_gold(mut q_hexes: Query<&mut Hex, Without<Blocked>>, time: Res<GameTime>) {
     for mut hex in q_hexes.iter_mut() {
-        if hex.timer.tick(time.delta()).just_finished() && hex.gold < hex.max_gold {
+        let delta = time.delta().mul_f32(hex.terrain.regen_rate());
+        if hex.timer.tick(delta).just_finished() && hex.gold < hex.max_gold {
             hex.gold += 1;
         }
     }
 }
 
-fn spawn_hexes_circle(mut ev_spawn: EventWriter<HexSpawnEvent>) {
-    let r = 4;
-    let hex = HexCoords::new();
-
-    for i in 0..r {
-        let ring = hex.get_ring(i);
-        for h in ring.iter() {
-            ev_spawn.send(HexSpawnEvent { coords: *h });
-        }
-    }
-}
-
 // fn spawn_hexes_simple_diamond(mut ev_spawn: EventWriter<HexSpawnEvent>) {
 //     for i in -3..=3 {
 //         for j in -3..=3 {
@@ -108,76 +129,153 @@ fn spawn_hexes_circle(mut ev_spawn: EventWriter<HexSpawnEvent>) {
 //     }
 // }
 
-struct TimedHexSpawner {
-    timer: Timer,
-    radius: u32,
-    ring: Vec<HexCoords>,
+/// Adds rings to the edge of the board over time
+/// Set up by the map
+pub struct HexGrowth {
+    pub timer: Timer,
+    pub radius: u32,
+    pub max_radius: u32,
+    pub ring_time: f32,
+    pub ring: Vec<HexCoords>,
+    pub defaults: HexDefaults,
 }
 
-impl Default for TimedHexSpawner {
-    fn default() -> Self {
-        TimedHexSpawner {
+impl HexGrowth {
+    pub fn new(growth: &MapGrowth, defaults: HexDefaults) -> Self {
+        HexGrowth {
+            timer: Timer::from_seconds(3.0, true),
+            radius: growth.start_radius,
+            max_radius: growth.max_radius,
+            ring_time: growth.ring_time,
+            ring: Vec::new(),
+            defaults,
+        }
+    }
+
+    // a map that doesn't grow
+    pub fn none() -> Self {
+        HexGrowth {
             timer: Timer::from_seconds(3.0, true),
-            radius: 4,
+            radius: 1,
+            max_radius: 0,
+            ring_time: 60.0,
             ring: Vec::new(),
+            defaults: HexDefaults::default(),
         }
     }
 }
 
 fn spawn_ring_over_time(
-    mut local: Local<TimedHexSpawner>,
-    time: Res<Time>,
+    mut growth: ResMut<HexGrowth>,
+    time: Res<GameTime>,
     mut ev_spawn: EventWriter<HexSpawnEvent>,
 ) {
-    // don't grow forever
-    if local.radius > 10 {
-        return;
-    }
-
-    // it should build a whole side in 10s
-    // a ring should take a minute
-    // timer = 60 / len
-    if local.ring.is_empty() {
-        local.ring = HexCoords::new().get_ring(local.radius);
-        local.ring.reverse();
-        let len = local.ring.len();
+    if growth.ring.is_empty() {
+        // don't grow forever
+        if growth.radius > growth.max_radius {
+            return;
+        }
+
+        // it should build a whole side in 10s
+        // a ring should take a minute
+        // timer = 60 / len
+        growth.ring = HexCoords::new().get_ring(growth.radius);
+        growth.ring.reverse();
+        let len = growth.ring.len();
         if len > 0 {
-            local.timer = Timer::from_seconds(60.0 / len as f32, true);
+            growth.timer = Timer::from_seconds(growth.ring_time / len as f32, true);
         }
-        local.radius += 1;
+        growth.radius += 1;
     }
 
-    if local.timer.tick(time.delta()).just_finished() {
-        let h = local.ring.pop();
+    if growth.timer.tick(time.delta()).just_finished() {
+        let h = growth.ring.pop();
         if let Some(h) = h {
-            ev_spawn.send(HexSpawnEvent { coords: h });
+            ev_spawn.send(growth.defaults.spawn_event(h));
         }
     }
 }
 
-fn spawn_hex(
+/// One mesh and a few materials shared by every hex
+/// Tiles swap handles instead of each owning their own
+#[derive(Default)]
+pub struct HexAssets {
+    mesh: Mesh2dHandle,
+    selected: Handle<ColorMaterial>,
+    terrain: HashMap<Terrain, Handle<ColorMaterial>>,
+}
+
+impl HexAssets {
+    pub fn material(&self, terrain: Terrain) -> Handle<ColorMaterial> {
+        self.terrain.get(&terrain).cloned().unwrap_or_default()
+    }
+}
+
+pub fn setup_hex_assets(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(HexAssets {
+        mesh: meshes
+            .add(shape::RegularPolygon::new(HEX_RADIUS - HEX_MARGIN, 6).into())
+            .into(),
+        selected: materials.add(ColorMaterial::from(Color::ANTIQUE_WHITE)),
+        terrain: Terrain::ALL
+            .iter()
+            .map(|&t| (t, materials.add(ColorMaterial::from(t.color()))))
+            .collect(),
+    });
+}
+
+// back to the menu. Clear the board and build it again from the map
+// towers and piles sit on hexes, so they go too
+fn reset_board(
+    mut commands: Commands,
+    q_hexes: Query<Entity, With<Hex>>,
+    mut hex_collect: ResMut<HexCollection>,
+    mut growth: ResMut<HexGrowth>,
+    mut selection: ResMut<HexSelection>,
+) {
+    for ent in q_hexes.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+    hex_collect.hexes.clear();
+    *growth = HexGrowth::none();
+    *selection = HexSelection::default();
+}
+
+pub fn spawn_hex(
+    mut commands: Commands,
+    hex_assets: Res<HexAssets>,
     mut ev_spawn: EventReader<HexSpawnEvent>,
-    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
     mut hex_collect: ResMut<HexCollection>,
 ) {
     for (i, ev) in ev_spawn.iter().enumerate() {
         //println!("HexSpawnEvent");
+        if hex_collect.hexes.contains_key(&ev.coords) {
+            // already on the board
+            continue;
+        }
+
         let entity = commands
             .spawn_bundle(MaterialMesh2dBundle {
-                mesh: meshes
-                    .add(shape::RegularPolygon::new(HEX_RADIUS - HEX_MARGIN, 6).into())
-                    .into(),
-                material: materials.add(ColorMaterial::from(Color::rgb(0.6, 0.2, 0.7))),
+                mesh: hex_assets.mesh.clone(),
+                material: hex_assets.material(ev.terrain),
                 transform: Transform::from_translation(
                     ev.coords.to_position().extend(0.0) + Vec3::new(0.0, -100.0, 0.0),
                 )
                 .with_rotation(Quat::from_rotation_z(30.0 * DEG_TO_RAD)),
                 ..default()
             })
-            .insert(Hex::new(HEX_RADIUS, ev.coords))
+            .insert(Hex::new(
+                ev.coords,
+                ev.terrain,
+                ev.gold,
+                ev.max_gold,
+                ev.regen_time,
+            ))
             .insert(HexMover {
                 start: ev.coords.to_position().extend(0.0) + Vec3::new(0.0, -100.0, 0.0),
                 target: ev.coords.to_position().extend(0.0),
@@ -185,7 +283,15 @@ fn spawn_hex(
             })
             .with_children(|parent| {
                 parent.spawn_bundle(SpriteBundle {
-                    texture: asset_server.load("sprites/Hex_15_13.png"),
+                    texture: game_assets.terrain(ev.terrain),
+                    sprite: Sprite {
+                        color: if ev.blocked {
+                            Color::DARK_GRAY
+                        } else {
+                            ev.terrain.sprite_color()
+                        },
+                        ..default()
+                    },
                     transform: Transform {
                         // spawn on top of the underlying hex
                         translation: Vec3 {
@@ -202,6 +308,10 @@ fn spawn_hex(
             })
             .id();
 
+        if ev.blocked {
+            commands.entity(entity).insert(Blocked);
+        }
+
         hex_collect.hexes.insert(ev.coords, entity);
     }
 }
@@ -248,20 +358,31 @@ fn info_panel(
     mut commands: Commands,
     input: Res<Input<MouseButton>>,
     window: Res<Windows>,
-    asset_server: Res<AssetServer>,
-    q_selection: Query<(Option<&Tower>, Option<&GoldPile>), With<Selection>>,
+    game_assets: Res<GameAssets>,
+    q_hexes: Query<(&Hex, Option<&Tower>, Option<&GoldPile>, Option<&Blocked>)>,
+    selection: Res<HexSelection>,
+    hex_collect: Res<HexCollection>,
 ) {
     if input.just_pressed(MouseButton::Right) {
         let win = window.get_primary().unwrap();
         if let Some(screen_pos) = win.cursor_position() {
-            let font = asset_server.load("fonts/FiraSans-Bold.ttf");
-            let mut text = "This is a Hex";
-            for (tower, gold_pile) in q_selection.iter() {
-                if tower.is_some() {
-                    text = "This is a Tower";
+            let font = game_assets.font.clone();
+            let mut text = "This is a Hex".to_string();
+            // only describe the hex under the mouse, not the whole brush
+            let hovered = selection
+                .hovered
+                .and_then(|c| hex_collect.hexes.get(&c))
+                .and_then(|&e| q_hexes.get(e).ok());
+            if let Some((hex, tower, gold_pile, blocked)) = hovered {
+                text = if blocked.is_some() {
+                    "This Hex is blocked".to_string()
+                } else if tower.is_some() {
+                    "This is a Tower".to_string()
                 } else if gold_pile.is_some() {
-                    text = "This is a Gold Pile";
-                }
+                    "This is a Gold Pile".to_string()
+                } else {
+                    format!("This is a Hex\n{}", hex.terrain.description())
+                };
             }
             commands
                 .spawn_bundle(NodeBundle {
@@ -347,55 +468,140 @@ fn remove_old_panel(
 #[derive(Component)]
 pub struct Selection;
 
+// only touches hexes that were just selected or deselected
 fn highlight_selection(
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut q_hex: Query<(&mut Handle<ColorMaterial>, Option<&Selection>), With<Hex>>,
+    hex_assets: Res<HexAssets>,
+    mut q_hex: Query<(&mut Handle<ColorMaterial>, &Hex)>,
+    q_selected: Query<Entity, (With<Hex>, Added<Selection>)>,
+    removed_selection: RemovedComponents<Selection>,
 ) {
-    for (color_handle, select) in q_hex.iter_mut() {
-        if select.is_some() {
-            let mut color_mat = materials.get_mut(&color_handle).unwrap();
-            color_mat.color = Color::ANTIQUE_WHITE;
+    for ent in removed_selection.iter() {
+        if let Ok((mut material, hex)) = q_hex.get_mut(ent) {
+            *material = hex_assets.material(hex.terrain);
+        }
+    }
+
+    for ent in q_selected.iter() {
+        if let Ok((mut material, _)) = q_hex.get_mut(ent) {
+            *material = hex_assets.selected.clone();
+        }
+    }
+}
+
+// biggest brush is 3 rings around the hovered hex
+const MAX_BRUSH: u32 = 3;
+// a shift click that moves less than this isn't a drag
+const DRAG_THRESHOLD: f32 = 10.0;
+
+/// Which hexes are selected
+///
+/// The brush around the mouse is always selected.
+/// Shift click and shift drag pick more that stay selected
+/// until Escape.
+#[derive(Default)]
+pub struct HexSelection {
+    picked: HashSet<HexCoords>,
+    // the hex under the mouse, if there is one
+    pub hovered: Option<HexCoords>,
+    // rings around the hovered hex that are also selected
+    pub brush: u32,
+    // where a shift drag started
+    drag_start: Option<Vec2>,
+}
+
+impl HexSelection {
+    fn brush_area(&self) -> Vec<HexCoords> {
+        match self.hovered {
+            Some(h) => h.spiral(self.brush),
+            None => Vec::new(),
+        }
+    }
+
+    // picked or picked and unpicked again
+    fn toggle(&mut self, coords: Vec<HexCoords>) {
+        if coords.iter().all(|c| self.picked.contains(c)) {
+            for c in coords.iter() {
+                self.picked.remove(c);
+            }
         } else {
-            let mut color_mat = materials.get_mut(&color_handle).unwrap();
-            color_mat.color = Color::rgb(0.388, 0.78, 0.3);
+            self.picked.extend(coords);
         }
     }
 }
 
+/// Hexes with their centers inside the box between 2 corners
+pub fn hexes_in_box(a: Vec2, b: Vec2, hexes: impl Iterator<Item = HexCoords>) -> Vec<HexCoords> {
+    let min = a.min(b);
+    let max = a.max(b);
+    hexes
+        .filter(|c| {
+            let p = c.to_position();
+            p.cmpge(min).all() && p.cmple(max).all()
+        })
+        .collect()
+}
+
 fn select_hex(
     mut commands: Commands,
-    q_hex: Query<(Entity, &Transform, &Hex)>,
-    q_selection: Query<(Entity, &Transform, &Hex), With<Selection>>,
+    mut selection: ResMut<HexSelection>,
+    q_selection: Query<(Entity, &Hex), With<Selection>>,
     mouse: Res<MouseWorldPos>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    hex_collect: Res<HexCollection>,
 ) {
-    for (ent, trans, hex) in q_selection.iter() {
-        if collide(
-            mouse.0.extend(0.0),
-            Vec2::new(0.1, 0.1),
-            trans.translation,
-            Vec2::new(1.6 * hex.radius, 1.8 * hex.radius),
-        )
-        .is_some()
-        {
-            return;
-        } else {
-            commands.entity(ent).remove::<Selection>();
+    // which hex the mouse is in, if that hex exists
+    let coords = HexCoords::from_position(mouse.0);
+    selection.hovered = hex_collect.hexes.get(&coords).map(|_| coords);
+
+    if keys.just_pressed(KeyCode::RBracket) {
+        selection.brush = (selection.brush + 1).min(MAX_BRUSH);
+    }
+    if keys.just_pressed(KeyCode::LBracket) {
+        selection.brush = selection.brush.saturating_sub(1);
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        selection.picked.clear();
+        selection.drag_start = None;
+    }
+
+    let shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    if shift && buttons.just_pressed(MouseButton::Left) {
+        selection.drag_start = Some(mouse.0);
+    }
+
+    // hexes inside the box being dragged
+    let mut dragged = Vec::new();
+    if let Some(start) = selection.drag_start {
+        if start.distance(mouse.0) > DRAG_THRESHOLD {
+            dragged = hexes_in_box(start, mouse.0, hex_collect.hexes.keys().copied());
+        }
+
+        if !buttons.pressed(MouseButton::Left) {
+            // let go
+            selection.drag_start = None;
+            if start.distance(mouse.0) > DRAG_THRESHOLD {
+                selection.picked.extend(dragged.drain(..));
+            } else {
+                let area = selection.brush_area();
+                selection.toggle(area);
+            }
         }
     }
 
-    for (ent, trans, hex) in q_hex.iter() {
-        // bounding box for hexes is close enough
-        // 1.6 so you don't select multiple.
-        if collide(
-            mouse.0.extend(0.0),
-            Vec2::new(0.1, 0.1),
-            trans.translation,
-            Vec2::new(1.6 * hex.radius, 1.6 * hex.radius),
-        )
-        .is_some()
-        {
+    let mut wanted: HashSet<HexCoords> = selection.picked.clone();
+    wanted.extend(dragged);
+    wanted.extend(selection.brush_area());
+
+    // only change the hexes that changed
+    for (ent, hex) in q_selection.iter() {
+        if !wanted.remove(&hex.coords) {
+            commands.entity(ent).remove::<Selection>();
+        }
+    }
+    for coords in wanted {
+        if let Some(&ent) = hex_collect.hexes.get(&coords) {
             commands.entity(ent).insert(Selection);
-            return;
         }
     }
 }
@@ -410,7 +616,9 @@ fn test_from_pos(input: Res<Input<KeyCode>>, mouse: Res<MouseWorldPos>) {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+// written as (u, v) in map and save files
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(from = "(isize, isize)", into = "(isize, isize)")]
 pub struct HexCoords {
     // u is left-right offset
     // pos u is north-east
@@ -428,6 +636,22 @@ impl HexCoords {
         HexCoords { u: 0, v: 0 }
     }
 
+    #[allow(dead_code)]
+    pub fn from_axial(u: isize, v: isize) -> Self {
+        HexCoords { u, v }
+    }
+
+    // cube coords are (u, v, w) where u + v + w = 0
+    // w is implied so we only store u and v
+    pub fn from_cube(u: isize, v: isize, w: isize) -> Self {
+        debug_assert_eq!(u + v + w, 0, "cube coords must sum to 0");
+        HexCoords { u, v }
+    }
+
+    pub fn to_cube(self) -> (isize, isize, isize) {
+        (self.u, self.v, -self.u - self.v)
+    }
+
     pub fn to_position(self) -> Vec2 {
         Vec2::new(
             (HEX_RADIUS + HEX_RADIUS * 0.5) * (self.u as f32),
@@ -438,17 +662,14 @@ impl HexCoords {
         )
     }
 
+    /// The hex that contains pos
     pub fn from_position(pos: Vec2) -> Self {
-        // seems to work well enough
-        // at least as consistent as selection
-
+        // undo to_position to get fractional coords
+        // then let cube rounding find the hex
         let u = pos.x / (HEX_RADIUS + HEX_RADIUS * 0.5);
         let v = (pos.y - HEX_SPACING * HEX_RADIUS * u) / (2.0 * HEX_SPACING * HEX_RADIUS);
         //println!("(u, v) f32: ({:?}, {:?})", u, v);
-        HexCoords {
-            u: u.round() as isize,
-            v: v.round() as isize,
-        }
+        HexCoords::round(u, v)
     }
 
     // pub fn equals(self, other: HexCoords) -> bool {
@@ -504,6 +725,7 @@ impl HexCoords {
     /// # Arguments
     ///
     /// * `radius` - the radius of the ring around you.
+    ///
     /// Returned in clockwise order starting with the north hex
     ///
     /// Radius of 0 is self.
@@ -570,4 +792,401 @@ impl HexCoords {
     pub fn get_neighbours(self) -> Vec<HexCoords> {
         self.get_ring(1)
     }
+
+    /// Number of steps between two hexes
+    #[allow(dead_code)]
+    pub fn distance(self, other: HexCoords) -> u32 {
+        let (au, av, aw) = self.to_cube();
+        let (bu, bv, bw) = other.to_cube();
+        // in cube space the largest axis difference is the distance
+        ((au - bu).abs().max((av - bv).abs()).max((aw - bw).abs())) as u32
+    }
+
+    /// Rounds fractional axial coords to the hex that contains them
+    ///
+    /// Rounding u and v on their own picks the wrong hex near the edges.
+    /// Round all 3 cube coords, then fix up the one that moved the most
+    /// so they still add up to 0.
+    pub fn round(u: f32, v: f32) -> HexCoords {
+        let w = -u - v;
+        let mut ru = u.round();
+        let mut rv = v.round();
+        let rw = w.round();
+
+        let du = (ru - u).abs();
+        let dv = (rv - v).abs();
+        let dw = (rw - w).abs();
+
+        if du > dv && du > dw {
+            ru = -rv - rw;
+        } else if dv > dw {
+            rv = -ru - rw;
+        }
+        // else w is the odd one out, and it's implied anyway
+
+        HexCoords {
+            u: ru as isize,
+            v: rv as isize,
+        }
+    }
+
+    /// Fractional axial coords t of the way from self to other
+    #[allow(dead_code)]
+    pub fn lerp(self, other: HexCoords, t: f32) -> (f32, f32) {
+        (
+            self.u as f32 + (other.u - self.u) as f32 * t,
+            self.v as f32 + (other.v - self.v) as f32 * t,
+        )
+    }
+
+    /// Every hex on a straight line from self to other, both ends included
+    #[allow(dead_code)]
+    pub fn line_to(self, other: HexCoords) -> Vec<HexCoords> {
+        let n = self.distance(other);
+        if n == 0 {
+            return vec![self];
+        }
+
+        // nudge the ends so lines along hex edges
+        // always round to the same side
+        let a = (self.u as f32 + 1e-6, self.v as f32 + 1e-6);
+        let b = (other.u as f32 + 1e-6, other.v as f32 + 1e-6);
+
+        (0..=n)
+            .map(|i| {
+                let t = i as f32 / n as f32;
+                HexCoords::round(a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+            })
+            .collect()
+    }
+
+    /// Every hex within radius of self, including self
+    #[allow(dead_code)]
+    pub fn range(self, radius: u32) -> Vec<HexCoords> {
+        let r = radius as isize;
+        let mut output = Vec::new();
+        for du in -r..=r {
+            for dv in (-r).max(-du - r)..=r.min(-du + r) {
+                output.push(HexCoords {
+                    u: self.u + du,
+                    v: self.v + dv,
+                });
+            }
+        }
+        output
+    }
+
+    /// Same hexes as range, but ordered ring by ring
+    /// starting with self, then the north hex of each ring going clockwise
+    pub fn spiral(self, radius: u32) -> Vec<HexCoords> {
+        let mut output = Vec::new();
+        for i in 0..=radius {
+            output.append(&mut self.get_ring(i));
+        }
+        output
+    }
+
+    /// Rotate around pivot in steps of 60 degrees
+    /// positive is clockwise
+    #[allow(dead_code)]
+    pub fn rotate(self, pivot: HexCoords, steps: i32) -> HexCoords {
+        let (mut u, mut v, mut w) = (self - pivot).to_cube();
+        for _ in 0..steps.rem_euclid(6) {
+            // clockwise. north (0, 1, -1) -> north east (1, 0, -1)
+            (u, v, w) = (-w, -u, -v);
+        }
+        HexCoords::from_cube(u, v, w) + pivot
+    }
+
+    /// Mirror across a line through pivot
+    ///
+    /// axis is the cube coord that keeps its value. The other 2 swap.
+    /// Mirroring across U swaps north and south.
+    #[allow(dead_code)]
+    pub fn reflect(self, pivot: HexCoords, axis: HexAxis) -> HexCoords {
+        let (u, v, w) = (self - pivot).to_cube();
+        let (u, v, w) = match axis {
+            HexAxis::U => (u, w, v),
+            HexAxis::V => (w, v, u),
+            HexAxis::W => (v, u, w),
+        };
+        HexCoords::from_cube(u, v, w) + pivot
+    }
+}
+
+impl Default for HexCoords {
+    fn default() -> Self {
+        HexCoords::new()
+    }
+}
+
+impl From<(isize, isize)> for HexCoords {
+    fn from((u, v): (isize, isize)) -> Self {
+        HexCoords { u, v }
+    }
+}
+
+impl From<HexCoords> for (isize, isize) {
+    fn from(h: HexCoords) -> Self {
+        (h.u, h.v)
+    }
+}
+
+impl std::ops::Add for HexCoords {
+    type Output = HexCoords;
+
+    fn add(self, other: HexCoords) -> HexCoords {
+        HexCoords {
+            u: self.u + other.u,
+            v: self.v + other.v,
+        }
+    }
+}
+
+impl std::ops::Sub for HexCoords {
+    type Output = HexCoords;
+
+    fn sub(self, other: HexCoords) -> HexCoords {
+        HexCoords {
+            u: self.u - other.u,
+            v: self.v - other.v,
+        }
+    }
+}
+
+/// The cube axis that stays fixed when reflecting
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HexAxis {
+    U,
+    V,
+    W,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn h(u: isize, v: isize) -> HexCoords {
+        HexCoords::from_axial(u, v)
+    }
+
+    #[test]
+    fn ring_one_is_clockwise_from_north() {
+        let ring = HexCoords::new().get_ring(1);
+        assert_eq!(
+            ring,
+            vec![h(0, 1), h(1, 0), h(1, -1), h(0, -1), h(-1, 0), h(-1, 1)]
+        );
+    }
+
+    #[test]
+    fn ring_two_order() {
+        let ring = HexCoords::new().get_ring(2);
+        assert_eq!(ring.len(), 12);
+        assert_eq!(ring[0], h(0, 2));
+        assert_eq!(ring[1], h(1, 1));
+        assert_eq!(ring[2], h(2, 0));
+        assert_eq!(ring[4], h(2, -2));
+        assert_eq!(ring[11], h(-1, 2));
+    }
+
+    #[test]
+    fn ring_zero_is_self() {
+        let c = h(3, -2);
+        assert_eq!(c.get_ring(0), vec![c]);
+    }
+
+    #[test]
+    fn ring_hexes_are_radius_away() {
+        let c = h(2, -5);
+        for r in 0..6 {
+            let ring = c.get_ring(r);
+            assert_eq!(ring.len(), if r == 0 { 1 } else { 6 * r as usize });
+            for x in ring {
+                assert_eq!(c.distance(x), r);
+            }
+        }
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(h(0, 0).distance(h(0, 0)), 0);
+        assert_eq!(h(0, 0).distance(h(0, 3)), 3);
+        assert_eq!(h(0, 0).distance(h(2, -1)), 2);
+        assert_eq!(h(-2, 3).distance(h(1, -1)), 4);
+        assert_eq!(h(1, 1).distance(h(-1, -1)), h(-1, -1).distance(h(1, 1)));
+    }
+
+    #[test]
+    fn cube_round_trip() {
+        for c in h(1, -1).range(3) {
+            let (u, v, w) = c.to_cube();
+            assert_eq!(u + v + w, 0);
+            assert_eq!(HexCoords::from_cube(u, v, w), c);
+        }
+    }
+
+    #[test]
+    fn round_fixes_largest_error() {
+        assert_eq!(HexCoords::round(0.1, -0.1), h(0, 0));
+        assert_eq!(HexCoords::round(0.9, 0.05), h(1, 0));
+        // rounding u and v separately gives (1, 1)
+        // which is 2 away from both of the hexes this is between
+        assert_eq!(HexCoords::round(0.6, 0.6).distance(h(0, 1)), 1);
+        assert_eq!(HexCoords::round(0.6, 0.6), h(1, 0));
+    }
+
+    // flat topped hexagon with HEX_RADIUS corners
+    fn in_hexagon(center: Vec2, p: Vec2) -> bool {
+        let d = (p - center).abs();
+        let h = HEX_SPACING * HEX_RADIUS;
+        d.y <= h && HEX_SPACING * d.x + 0.5 * d.y <= h
+    }
+
+    #[test]
+    fn from_position_centers() {
+        for c in HexCoords::new().range(10) {
+            assert_eq!(HexCoords::from_position(c.to_position()), c);
+        }
+    }
+
+    #[test]
+    fn every_point_in_a_hex_maps_to_it() {
+        // walk a polar grid inside each hexagon, right up to the edges
+        for c in h(2, -1).range(4) {
+            let center = c.to_position();
+            for corner in 0..6 {
+                for step in 0..12 {
+                    let angle = (corner as f32 * 60.0 + step as f32 * 5.0) * DEG_TO_RAD;
+                    let dir = Vec2::new(angle.cos(), angle.sin());
+                    for i in 0..=20 {
+                        let p = center + dir * HEX_RADIUS * i as f32 / 20.0;
+                        if !in_hexagon(center, p) {
+                            break;
+                        }
+                        // stay clear of the shared edge itself
+                        if !in_hexagon(center, center + (p - center) * 1.001) {
+                            continue;
+                        }
+                        assert_eq!(HexCoords::from_position(p), c, "point {:?}", p);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_point_maps_to_a_hex_that_contains_it() {
+        let mut x = -200.0;
+        while x < 200.0 {
+            let mut y = -200.0;
+            while y < 200.0 {
+                let p = Vec2::new(x, y);
+                let c = HexCoords::from_position(p);
+                assert!(
+                    in_hexagon(c.to_position(), p * 0.9999 + c.to_position() * 0.0001),
+                    "point {:?} picked {:?}",
+                    p,
+                    c
+                );
+                y += 3.7;
+            }
+            x += 3.3;
+        }
+    }
+
+    #[test]
+    fn lerp_ends() {
+        let a = h(-2, 1);
+        let b = h(3, -4);
+        assert_eq!(a.lerp(b, 0.0), (-2.0, 1.0));
+        assert_eq!(a.lerp(b, 1.0), (3.0, -4.0));
+    }
+
+    #[test]
+    fn line_is_connected() {
+        let a = h(-3, 1);
+        let b = h(4, -2);
+        let line = a.line_to(b);
+        assert_eq!(line.len() as u32, a.distance(b) + 1);
+        assert_eq!(line[0], a);
+        assert_eq!(*line.last().unwrap(), b);
+        for pair in line.windows(2) {
+            assert_eq!(pair[0].distance(pair[1]), 1);
+        }
+        assert_eq!(a.line_to(a), vec![a]);
+    }
+
+    #[test]
+    fn range_matches_spiral() {
+        let c = h(1, 2);
+        for r in 0..5 {
+            let mut range = c.range(r);
+            let mut spiral = c.spiral(r);
+            assert_eq!(range.len() as u32, 3 * r * (r + 1) + 1);
+            range.sort_by_key(|x| (x.u, x.v));
+            spiral.sort_by_key(|x| (x.u, x.v));
+            assert_eq!(range, spiral);
+        }
+    }
+
+    #[test]
+    fn spiral_starts_with_self_then_rings() {
+        let c = h(0, 0);
+        let spiral = c.spiral(2);
+        assert_eq!(spiral[0], c);
+        assert_eq!(spiral[1..7], c.get_ring(1)[..]);
+        assert_eq!(spiral[7..], c.get_ring(2)[..]);
+    }
+
+    #[test]
+    fn box_selects_centers_inside() {
+        let board = HexCoords::new().spiral(3);
+        let a = HexCoords::from_axial(-1, 0).to_position();
+        let b = HexCoords::from_axial(1, 0).to_position();
+        let picked = hexes_in_box(a, b, board.iter().copied());
+        // the 4 corners and the center between them
+        assert_eq!(picked.len(), 5);
+        assert!(picked.contains(&HexCoords::new()));
+
+        // dragging the other way picks the same hexes
+        let mut backwards = hexes_in_box(b, a, board.iter().copied());
+        let mut picked = picked;
+        picked.sort_by_key(|&c| <(isize, isize)>::from(c));
+        backwards.sort_by_key(|&c| <(isize, isize)>::from(c));
+        assert_eq!(picked, backwards);
+    }
+
+    #[test]
+    fn rotate_walks_the_ring() {
+        let pivot = h(2, -1);
+        for r in 1..4 {
+            let ring = pivot.get_ring(r);
+            let len = ring.len();
+            for (i, &x) in ring.iter().enumerate() {
+                // one step clockwise is radius hexes along the ring
+                assert_eq!(x.rotate(pivot, 1), ring[(i + r as usize) % len]);
+                assert_eq!(x.rotate(pivot, -1), ring[(i + len - r as usize) % len]);
+                assert_eq!(x.rotate(pivot, 6), x);
+            }
+        }
+    }
+
+    #[test]
+    fn reflect() {
+        let o = h(0, 0);
+        // north <-> south across the u axis
+        assert_eq!(h(0, 1).reflect(o, HexAxis::U), h(0, -1));
+        // the mirror line for v is where u == w
+        assert_eq!(h(1, -2).reflect(o, HexAxis::V), h(1, -2));
+        assert_eq!(h(1, 0).reflect(o, HexAxis::V), h(-1, 0));
+        for x in h(3, 3).range(2) {
+            for axis in [HexAxis::U, HexAxis::V, HexAxis::W] {
+                let p = h(3, 3);
+                assert_eq!(x.reflect(p, axis).reflect(p, axis), x);
+                assert_eq!(x.reflect(p, axis).distance(p), x.distance(p));
+            }
+        }
+    }
 }
@@ -0,0 +1,132 @@
+use bevy::prelude::*;
+
+use crate::game_assets::GameAssets;
+use crate::GameState;
+
+const STARTING_LIVES: u32 = 10;
+
+pub struct LivesPlugin;
+
+impl Plugin for LivesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GoldEscapedEvent>()
+            .add_event::<GameOverEvent>()
+            .insert_resource(Lives::new())
+            .add_startup_system(lives_text)
+            .add_system(update_lives_text)
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(reset_lives))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(lose_lives)
+                    .with_system(game_over.after(lose_lives)),
+            );
+    }
+}
+
+/// How many more gold can get away before the game is lost
+pub struct Lives {
+    pub count: u32,
+    // what ended the last game
+    pub lost_to: Option<GameOverReason>,
+}
+
+impl Lives {
+    pub fn new() -> Self {
+        Lives {
+            count: STARTING_LIVES,
+            lost_to: None,
+        }
+    }
+}
+
+/// An enemy got off the map carrying gold
+pub struct GoldEscapedEvent;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameOverReason {
+    OutOfLives,
+    // enemies stole the last of the gold in the center pile
+    TreasuryDrained,
+}
+
+impl GameOverReason {
+    pub fn description(self) -> &'static str {
+        match self {
+            GameOverReason::OutOfLives => "Too much gold got away",
+            GameOverReason::TreasuryDrained => "The treasury was emptied",
+        }
+    }
+}
+
+pub struct GameOverEvent {
+    pub reason: GameOverReason,
+}
+
+fn lose_lives(
+    mut lives: ResMut<Lives>,
+    mut ev_escaped: EventReader<GoldEscapedEvent>,
+    mut ev_game_over: EventWriter<GameOverEvent>,
+) {
+    for _ev in ev_escaped.iter() {
+        if lives.count > 0 {
+            lives.count -= 1;
+            if lives.count == 0 {
+                ev_game_over.send(GameOverEvent {
+                    reason: GameOverReason::OutOfLives,
+                });
+            }
+        }
+    }
+}
+
+fn game_over(
+    mut lives: ResMut<Lives>,
+    mut ev_game_over: EventReader<GameOverEvent>,
+    mut state: ResMut<State<GameState>>,
+) {
+    // only the first reason counts
+    if let Some(ev) = ev_game_over.iter().next() {
+        lives.lost_to = Some(ev.reason);
+        // ignore it if something else already changed the state this frame
+        let _ = state.set(GameState::Lost);
+    }
+}
+
+fn reset_lives(mut lives: ResMut<Lives>) {
+    *lives = Lives::new();
+}
+
+#[derive(Component)]
+struct LivesText;
+
+fn lives_text(mut commands: Commands, game_assets: Res<GameAssets>) {
+    let font = game_assets.font.clone();
+
+    commands
+        .spawn_bundle(
+            TextBundle::from_sections([TextSection::from_style(TextStyle {
+                font,
+                font_size: 30.0,
+                color: Color::GOLD,
+            })])
+            .with_style(Style {
+                align_self: AlignSelf::FlexEnd,
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(5.0),
+                    right: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(LivesText);
+}
+
+fn update_lives_text(lives: Res<Lives>, mut q_text: Query<&mut Text, With<LivesText>>) {
+    if lives.is_changed() {
+        for mut text in q_text.iter_mut() {
+            text.sections[0].value = format!("Lives: {}", lives.count);
+        }
+    }
+}
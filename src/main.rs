@@ -5,32 +5,79 @@
 #![allow(clippy::too_many_arguments, clippy::type_complexity)]
 
 use bevy::{
+    app::PluginGroupBuilder,
     diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
     prelude::*,
-    render::camera::{RenderTarget, ScalingMode},
+    render::camera::{CameraProjection, RenderTarget, ScalingMode},
 };
 use bevy_rapier2d::prelude::*;
 
 mod boids;
+mod camera;
 mod enemies;
+mod enemy_types;
+mod game_assets;
+mod game_time;
 mod gold;
 mod hex;
+mod lives;
+mod map;
+mod mapgen;
 mod palette;
+mod pathfinding;
+mod replay;
+mod rng;
+mod save;
+mod sim;
+mod status;
+mod terrain;
+#[cfg(test)]
+mod test_app;
 mod tower;
 mod tutorial;
+mod waves;
 
 use crate::hex::HexPlugin;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
+    // cargo run --release -- simulate --minutes 20
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("simulate") {
+        match sim::SimSettings::from_args(&args[1..]) {
+            Ok(settings) => sim::run(settings),
+            Err(e) => {
+                eprintln!("{e}\n{}", sim::USAGE);
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+
+    // cargo run -- --seed 42 plays the same game again
+    let seed = match rng::seed_arg(&args) {
+        Ok(seed) => seed,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    // cargo run -- --replay replays/last.ron watches one that was saved with F8
+    let replay = match replay::replay_arg(&args) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
         .add_plugin(RapierDebugRenderPlugin::default())
-        .add_plugin(boids::BoidsPlugin)
-        .add_plugin(enemies::EnemyPlugin)
-        .add_plugin(gold::GoldPlugin)
-        .add_plugin(HexPlugin)
-        .add_plugin(tower::TowerPlugin)
+        .add_plugin(game_assets::GameAssetsPlugin)
+        .add_plugins(GamePlugins)
+        .add_plugin(camera::CameraPlugin)
+        .add_plugin(map::MapPlugin)
+        .add_plugin(save::SavePlugin)
         .add_plugin(tutorial::TutorialPlugin)
         .insert_resource(MouseWorldPos(Vec2::ONE * 10000.0))
         .insert_resource(RapierConfiguration {
@@ -44,12 +91,17 @@ fn main() {
             canvas: Some("#bevy".to_owned()),
             ..Default::default()
         })
-        .add_event::<StartSpawningEnemiesEvent>()
+        .add_state(GameState::Menu)
         .add_startup_system(setup)
-        .add_system(update_mouse_position)
+        .add_system(pause_input)
+        .add_system(
+            update_mouse_position
+                .after(camera::move_camera)
+                .before(replay::PlayerInput),
+        )
         //.add_system(fps)
         // // Adds frame time diagnostics
-        .add_plugin(FrameTimeDiagnosticsPlugin::default())
+        .add_plugin(FrameTimeDiagnosticsPlugin)
         .add_startup_system(infotext_system)
         .add_system(change_text_system)
         // // Adds a system that prints diagnostics to the console
@@ -59,7 +111,40 @@ fn main() {
         // .add_plugin(bevy::diagnostic::EntityCountDiagnosticsPlugin::default())
         // // Uncomment this to add an asset count diagnostics:
         // //.add_plugin(bevy::asset::diagnostic::AssetCountDiagnosticsPlugin::<Texture>::default())
-        .run();
+        ;
+    if let Some(seed) = seed {
+        app.insert_resource(rng::GameRng::new(seed));
+    }
+    if let Some(replay) = replay {
+        app.insert_resource(replay::Playback::new(replay));
+    }
+    app.run();
+}
+
+/// Everything that plays the game
+///
+/// Shared by the window and the headless simulation, so none of it
+/// can need a window, an asset server or a renderer.
+pub struct GamePlugins;
+
+impl PluginGroup for GamePlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
+            // first, the others add systems to its GameTick stage
+            .add(game_time::GameTimePlugin)
+            .add(boids::BoidsPlugin)
+            .add(enemies::EnemyPlugin)
+            .add(gold::GoldPlugin)
+            .add(HexPlugin)
+            .add(lives::LivesPlugin)
+            .add(pathfinding::PathfindingPlugin)
+            .add(replay::ReplayPlugin)
+            .add(rng::RngPlugin)
+            .add(status::StatusPlugin)
+            .add(tower::TowerPlugin)
+            .add(waves::WavePlugin);
+    }
 }
 
 pub const HEIGHT: f32 = 720.0;
@@ -67,7 +152,29 @@ pub const WIDTH: f32 = 1280.0;
 
 struct MouseWorldPos(Vec2);
 
-pub struct StartSpawningEnemiesEvent;
+/// Where the game is at
+///
+/// Paused and Won sit on top of Playing, so leaving them
+/// goes back to the same game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameState {
+    Menu,
+    Playing,
+    Paused,
+    Won,
+    Lost,
+}
+
+fn pause_input(keys: Res<Input<KeyCode>>, mut state: ResMut<State<GameState>>) {
+    if keys.just_pressed(KeyCode::P) {
+        // ignore it if something else already changed the state this frame
+        let _ = match state.current() {
+            GameState::Playing => state.push(GameState::Paused),
+            GameState::Paused => state.pop(),
+            _ => Ok(()),
+        };
+    }
+}
 
 fn setup(mut commands: Commands) {
     //commands.spawn_bundle(Camera2dBundle::default());
@@ -94,10 +201,10 @@ fn setup(mut commands: Commands) {
 
 fn update_mouse_position(
     windows: Res<Windows>,
-    q_camera: Query<(&Camera, &GlobalTransform)>,
+    q_camera: Query<(&Camera, &Transform, &OrthographicProjection)>,
     mut mouse_pos: ResMut<MouseWorldPos>,
 ) {
-    let (camera, camera_transform) = q_camera.single();
+    let (camera, camera_transform, projection) = q_camera.single();
 
     let win = if let RenderTarget::Window(id) = camera.target {
         windows.get(id).unwrap()
@@ -106,13 +213,16 @@ fn update_mouse_position(
     };
 
     if let Some(screen_pos) = win.cursor_position() {
-        let window_size = Vec2::new(win.width() as f32, win.height() as f32);
+        let window_size = Vec2::new(win.width(), win.height());
 
         // convert screen position [0..resolution] to ndc [-1..1] (gpu coords)
         let ndc = (screen_pos / window_size) * 2.0 - Vec2::ONE;
 
         // matrix for undoing the projection and camera transform
-        let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
+        // use this frame's transform and zoom. The GlobalTransform and
+        // camera.projection_matrix() aren't updated until after the camera moves
+        let ndc_to_world =
+            camera_transform.compute_matrix() * projection.get_projection_matrix().inverse();
 
         // use it to convert ndc to world-space coordinates
         let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
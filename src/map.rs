@@ -0,0 +1,328 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::gold::PileSpawnEvent;
+use crate::hex::{spawn_hex, HexCoords, HexGrowth, HexSpawnEvent};
+use crate::mapgen::{self, MapGen};
+use crate::terrain::Terrain;
+use crate::GameState;
+
+pub const DEFAULT_MAP: &str = "maps/island.map.ron";
+
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<HexMap>()
+            .init_asset_loader::<HexMapLoader>()
+            .add_startup_system(load_map)
+            // the hexes have to exist next frame, while the pile events are still around.
+            // see spawn_pile
+            .add_system(spawn_map.before(spawn_hex))
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(reset_map));
+    }
+}
+
+/// A board layout loaded from a .map.ron file
+#[derive(Deserialize, TypeUuid, Clone, Debug)]
+#[uuid = "5b9a3f3e-1c1e-4f0c-9a57-3b6f2d4c8e21"]
+pub struct HexMap {
+    // generate an island from a seed
+    #[serde(default)]
+    pub generator: Option<MapGen>,
+    // fill every hex within this radius of the center
+    #[serde(default)]
+    pub fill_radius: Option<u32>,
+    #[serde(default)]
+    pub defaults: HexDefaults,
+    #[serde(default)]
+    pub hexes: Vec<MapHex>,
+    #[serde(default)]
+    pub blocked: Vec<HexCoords>,
+    #[serde(default)]
+    pub piles: Vec<MapPile>,
+    #[serde(default)]
+    pub growth: Option<MapGrowth>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HexDefaults {
+    #[serde(default)]
+    pub terrain: Terrain,
+    pub gold: u32,
+    pub max_gold: u32,
+    pub regen_time: f32,
+}
+
+impl Default for HexDefaults {
+    fn default() -> Self {
+        HexDefaults {
+            terrain: Terrain::Grassland,
+            gold: 1,
+            max_gold: 3,
+            regen_time: 7.5,
+        }
+    }
+}
+
+impl HexDefaults {
+    pub fn spawn_event(&self, coords: HexCoords) -> HexSpawnEvent {
+        HexSpawnEvent {
+            coords,
+            terrain: self.terrain,
+            gold: self.gold,
+            max_gold: self.max_gold,
+            regen_time: self.regen_time,
+            blocked: false,
+        }
+    }
+}
+
+/// A hex that doesn't use the map defaults
+#[derive(Deserialize, Clone, Debug)]
+pub struct MapHex {
+    pub coords: HexCoords,
+    #[serde(default)]
+    pub terrain: Option<Terrain>,
+    #[serde(default)]
+    pub gold: Option<u32>,
+    #[serde(default)]
+    pub max_gold: Option<u32>,
+    #[serde(default)]
+    pub regen_time: Option<f32>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct MapPile {
+    pub coords: HexCoords,
+    #[serde(default)]
+    pub gold: u32,
+}
+
+/// Rings that get added to the edge of the board over time
+#[derive(Deserialize, Clone, Debug)]
+pub struct MapGrowth {
+    pub start_radius: u32,
+    pub max_radius: u32,
+    // seconds to spawn a whole ring
+    pub ring_time: f32,
+}
+
+impl HexMap {
+    pub fn from_ron(text: &str) -> Result<Self, ron::Error> {
+        ron::from_str(text)
+    }
+
+    /// One event per hex on the board
+    ///
+    /// The fill comes first in spiral order so the intro animation
+    /// still ripples out from the center.
+    pub fn spawn_events(&self) -> Vec<HexSpawnEvent> {
+        let mut events = MapEvents::default();
+
+        if let Some(settings) = &self.generator {
+            for hex in mapgen::generate(settings) {
+                events.add(self.hex_event(&hex));
+            }
+        }
+
+        if let Some(r) = self.fill_radius {
+            for coords in HexCoords::new().spiral(r) {
+                events.add(self.defaults.spawn_event(coords));
+            }
+        }
+
+        for hex in self.hexes.iter() {
+            events.add(self.hex_event(hex));
+        }
+
+        for &coords in self.blocked.iter() {
+            let mut ev = events
+                .get(coords)
+                .cloned()
+                .unwrap_or_else(|| self.defaults.spawn_event(coords));
+            ev.blocked = true;
+            events.add(ev);
+        }
+
+        events.events
+    }
+
+    /// The piles the game starts with
+    pub fn pile_events(&self) -> Vec<PileSpawnEvent> {
+        self.piles
+            .iter()
+            .map(|pile| PileSpawnEvent::with_gold(pile.coords, pile.gold))
+            .collect()
+    }
+
+    pub fn growth(&self) -> HexGrowth {
+        match &self.growth {
+            Some(g) => HexGrowth::new(g, self.defaults.clone()),
+            None => HexGrowth::none(),
+        }
+    }
+
+    // fill in whatever the hex doesn't set with the defaults
+    fn hex_event(&self, hex: &MapHex) -> HexSpawnEvent {
+        HexSpawnEvent {
+            coords: hex.coords,
+            terrain: hex.terrain.unwrap_or(self.defaults.terrain),
+            gold: hex.gold.unwrap_or(self.defaults.gold),
+            max_gold: hex.max_gold.unwrap_or(self.defaults.max_gold),
+            regen_time: hex.regen_time.unwrap_or(self.defaults.regen_time),
+            blocked: false,
+        }
+    }
+}
+
+// keeps the order hexes were added in
+// later entries for the same coords override earlier ones
+#[derive(Default)]
+struct MapEvents {
+    events: Vec<HexSpawnEvent>,
+    index: HashMap<HexCoords, usize>,
+}
+
+impl MapEvents {
+    fn add(&mut self, ev: HexSpawnEvent) {
+        if let Some(&i) = self.index.get(&ev.coords) {
+            self.events[i] = ev;
+        } else {
+            self.index.insert(ev.coords, self.events.len());
+            self.events.push(ev);
+        }
+    }
+
+    fn get(&self, coords: HexCoords) -> Option<&HexSpawnEvent> {
+        self.index.get(&coords).map(|&i| &self.events[i])
+    }
+}
+
+#[derive(Default)]
+pub struct HexMapLoader;
+
+impl AssetLoader for HexMapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let map = HexMap::from_ron(std::str::from_utf8(bytes)?)?;
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map.ron"]
+    }
+}
+
+/// The map the board is built from
+pub struct CurrentMap {
+    pub handle: Handle<HexMap>,
+    // the board is only built once the file has loaded
+    pub spawned: bool,
+}
+
+fn load_map(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(CurrentMap {
+        handle: asset_server.load(DEFAULT_MAP),
+        spawned: false,
+    });
+}
+
+pub fn spawn_map(
+    mut current: ResMut<CurrentMap>,
+    maps: Res<Assets<HexMap>>,
+    mut growth: ResMut<HexGrowth>,
+    mut ev_spawn: EventWriter<HexSpawnEvent>,
+    mut ev_pile: EventWriter<PileSpawnEvent>,
+) {
+    if current.spawned {
+        return;
+    }
+
+    if let Some(map) = maps.get(&current.handle) {
+        current.spawned = true;
+
+        ev_spawn.send_batch(map.spawn_events().into_iter());
+        ev_pile.send_batch(map.pile_events().into_iter());
+        *growth = map.growth();
+    }
+}
+
+// back to the menu. spawn_map builds the board again
+fn reset_map(mut current: ResMut<CurrentMap>) {
+    current.spawned = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_map_parses() {
+        let map = HexMap::from_ron(include_str!("../assets/maps/default.map.ron")).unwrap();
+        // rings 0..=3
+        assert_eq!(map.spawn_events().len(), 37);
+        assert_eq!(map.piles.len(), 1);
+        assert_eq!(map.piles[0].coords, HexCoords::new());
+        assert!(map.growth.is_some());
+    }
+
+    #[test]
+    fn island_map_parses() {
+        let map = HexMap::from_ron(include_str!("../assets/maps/island.map.ron")).unwrap();
+        let settings = map.generator.clone().unwrap();
+        assert_eq!(map.spawn_events().len(), mapgen::generate(&settings).len());
+        assert_eq!(map.piles[0].coords, HexCoords::new());
+    }
+
+    #[test]
+    fn overrides_and_blocked() {
+        let map = HexMap::from_ron(
+            "(
+                fill_radius: Some(1),
+                hexes: [
+                    (coords: (0, 1), max_gold: Some(9), terrain: Some(Water)),
+                    (coords: (5, 5), regen_time: Some(1.0)),
+                ],
+                blocked: [(0, 1), (-7, 0)],
+            )",
+        )
+        .unwrap();
+
+        let events = map.spawn_events();
+        assert_eq!(events.len(), 9);
+
+        let north = events
+            .iter()
+            .find(|e| e.coords == HexCoords::from_axial(0, 1))
+            .unwrap();
+        assert_eq!(north.max_gold, 9);
+        assert_eq!(north.terrain, Terrain::Water);
+        assert!(north.blocked);
+
+        let far = events
+            .iter()
+            .find(|e| e.coords == HexCoords::from_axial(5, 5))
+            .unwrap();
+        assert_eq!(far.regen_time, 1.0);
+        assert_eq!(far.max_gold, 3);
+        assert_eq!(far.terrain, Terrain::Grassland);
+        assert!(!far.blocked);
+
+        assert!(events
+            .iter()
+            .any(|e| e.blocked && e.coords.distance(HexCoords::new()) == 7));
+    }
+}
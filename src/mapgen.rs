@@ -0,0 +1,246 @@
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+use crate::hex::HexCoords;
+use crate::map::MapHex;
+use crate::terrain::Terrain;
+
+/// Settings for a generated island
+/// The same settings always make the same island
+#[derive(Deserialize, Clone, Debug)]
+pub struct MapGen {
+    pub seed: u64,
+    #[serde(default = "default_radius")]
+    pub radius: u32,
+    // patches of rock, water and wasteland
+    #[serde(default = "default_clusters")]
+    pub clusters: u32,
+    // winding lines of rich veins
+    #[serde(default = "default_veins")]
+    pub veins: u32,
+}
+
+fn default_radius() -> u32 {
+    7
+}
+
+fn default_clusters() -> u32 {
+    5
+}
+
+fn default_veins() -> u32 {
+    3
+}
+
+impl MapGen {
+    #[allow(dead_code)]
+    pub fn new(seed: u64) -> Self {
+        MapGen {
+            seed,
+            radius: default_radius(),
+            clusters: default_clusters(),
+            veins: default_veins(),
+        }
+    }
+}
+
+// always land with nothing special on it
+// so there's room for the center pile and the first towers
+const SAFE_RADIUS: u32 = 1;
+
+/// Builds an island out from the center
+///
+/// Hexes are returned in ring order, so the intro animation
+/// still ripples out from the middle.
+pub fn generate(settings: &MapGen) -> Vec<MapHex> {
+    // seeds are shared, so the same seed has to make the same island everywhere.
+    // ChaCha8's stream doesn't depend on the platform, but usize does:
+    // it's 32 bits on wasm. Every range is sampled as u32
+    let mut rng = ChaCha8Rng::seed_from_u64(settings.seed);
+
+    let island = grow_island(&mut rng, settings.radius);
+    let land: HashSet<HexCoords> = island.iter().copied().collect();
+
+    // every hex starts as grassland
+    let mut terrain: Vec<Terrain> = vec![Terrain::Grassland; island.len()];
+    let index = |c: HexCoords| island.iter().position(|&x| x == c);
+
+    // only hexes outside the safe zone can be changed
+    let candidates: Vec<HexCoords> = island
+        .iter()
+        .copied()
+        .filter(|c| c.distance(HexCoords::new()) > SAFE_RADIUS)
+        .collect();
+
+    if !candidates.is_empty() {
+        for _ in 0..settings.clusters {
+            let kind = *[Terrain::Rock, Terrain::Water, Terrain::Wasteland]
+                .choose(&mut rng)
+                .unwrap();
+            let size = rng.gen_range(2u32..=5) as usize;
+            let start = candidates[rng.gen_range(0..candidates.len() as u32) as usize];
+
+            // flood out from the start a random neighbour at a time
+            let mut cluster = vec![start];
+            while cluster.len() < size {
+                let from = cluster[rng.gen_range(0..cluster.len() as u32) as usize];
+                let next = from.get_neighbours()[rng.gen_range(0u32..6) as usize];
+                if land.contains(&next)
+                    && next.distance(HexCoords::new()) > SAFE_RADIUS
+                    && !cluster.contains(&next)
+                {
+                    cluster.push(next);
+                } else if rng.gen_bool(0.2) {
+                    // boxed in. Settle for a smaller cluster
+                    break;
+                }
+            }
+
+            for c in cluster {
+                if let Some(i) = index(c) {
+                    terrain[i] = kind;
+                }
+            }
+        }
+
+        for _ in 0..settings.veins {
+            let length = rng.gen_range(3u32..=6);
+            let mut c = candidates[rng.gen_range(0..candidates.len() as u32) as usize];
+            // veins mostly go in a straight line
+            let mut dir = rng.gen_range(0u32..6) as usize;
+            for _ in 0..length {
+                if let Some(i) = index(c) {
+                    terrain[i] = Terrain::RichVein;
+                }
+                if rng.gen_bool(0.3) {
+                    dir = (dir + if rng.gen_bool(0.5) { 1 } else { 5 }) % 6;
+                }
+                let next = c.get_neighbours()[dir];
+                if !land.contains(&next) || next.distance(HexCoords::new()) <= SAFE_RADIUS {
+                    break;
+                }
+                c = next;
+            }
+        }
+    }
+
+    island
+        .into_iter()
+        .zip(terrain)
+        .map(|(coords, terrain)| MapHex {
+            coords,
+            terrain: Some(terrain),
+            gold: None,
+            // veins hold more gold, but still only regrow 1 at a time
+            max_gold: if terrain == Terrain::RichVein {
+                Some(6)
+            } else {
+                None
+            },
+            regen_time: None,
+        })
+        .collect()
+}
+
+// a ring at a time, keep hexes that touch the land inside them
+// the further out, the more likely a hex is left as sea
+fn grow_island(rng: &mut ChaCha8Rng, radius: u32) -> Vec<HexCoords> {
+    let center = HexCoords::new();
+    let mut island = center.spiral(SAFE_RADIUS.min(radius));
+    let mut land: HashSet<HexCoords> = island.iter().copied().collect();
+
+    for r in (SAFE_RADIUS + 1)..=radius {
+        let keep = 1.0 - 0.7 * (r as f64 / radius as f64).powi(2);
+        for c in center.get_ring(r) {
+            let touches_land = c.get_neighbours().iter().any(|n| land.contains(n));
+            if touches_land && rng.gen_bool(keep) {
+                land.insert(c);
+                island.push(c);
+            }
+        }
+    }
+
+    island
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(hexes: &[MapHex]) -> Vec<(HexCoords, Option<Terrain>)> {
+        hexes.iter().map(|h| (h.coords, h.terrain)).collect()
+    }
+
+    #[test]
+    fn same_seed_same_map() {
+        for seed in [0, 1, 42, 9999] {
+            let a = generate(&MapGen::new(seed));
+            let b = generate(&MapGen::new(seed));
+            assert_eq!(layout(&a), layout(&b));
+        }
+    }
+
+    #[test]
+    fn different_seeds_differ() {
+        let a = generate(&MapGen::new(1));
+        let b = generate(&MapGen::new(2));
+        assert_ne!(layout(&a), layout(&b));
+    }
+
+    #[test]
+    fn center_is_safe() {
+        for seed in 0..50 {
+            let hexes = generate(&MapGen::new(seed));
+            for c in HexCoords::new().spiral(SAFE_RADIUS) {
+                let h = hexes.iter().find(|h| h.coords == c).unwrap();
+                assert_eq!(h.terrain, Some(Terrain::Grassland), "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn island_is_connected_and_in_radius() {
+        for seed in 0..50 {
+            let settings = MapGen::new(seed);
+            let hexes = generate(&settings);
+            let land: HashSet<HexCoords> = hexes.iter().map(|h| h.coords).collect();
+            assert_eq!(land.len(), hexes.len(), "no duplicates");
+
+            let mut seen = HashSet::new();
+            let mut open = vec![HexCoords::new()];
+            while let Some(c) = open.pop() {
+                if seen.insert(c) {
+                    for n in c.get_neighbours() {
+                        if land.contains(&n) {
+                            open.push(n);
+                        }
+                    }
+                }
+            }
+            assert_eq!(seen.len(), land.len(), "seed {}", seed);
+            assert!(land
+                .iter()
+                .all(|c| c.distance(HexCoords::new()) <= settings.radius));
+        }
+    }
+
+    #[test]
+    fn regression_seed_1() {
+        // if this changes, shared seeds make different maps
+        // only update it on purpose
+        let hexes = generate(&MapGen::new(1));
+        let count = |t: Terrain| hexes.iter().filter(|h| h.terrain == Some(t)).count();
+        assert_eq!(
+            (
+                hexes.len(),
+                count(Terrain::RichVein),
+                count(Terrain::Rock),
+                count(Terrain::Water),
+                count(Terrain::Wasteland),
+            ),
+            (89, 8, 11, 0, 3)
+        );
+    }
+}
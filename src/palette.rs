@@ -6,97 +6,97 @@ use bevy::prelude::*;
 // though Color::hex(#fbd439) would work
 #[allow(dead_code)]
 pub const YELLOW_WHITE: Color = Color::rgb(
-    (0xfb as f32 / 0xff as f32) as f32,
+    0xfb as f32 / 0xff as f32,
     0xff as f32,
-    (0xce as f32 / 0xff as f32) as f32,
+    0xce as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const GOLD: Color = Color::rgb(
-    (0xfb as f32 / 0xff as f32) as f32,
-    (0xd4 as f32 / 0xff as f32) as f32,
-    (0x39 as f32 / 0xff as f32) as f32,
+    0xfb as f32 / 0xff as f32,
+    0xd4 as f32 / 0xff as f32,
+    0x39 as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const ORANGE: Color = Color::rgb(
-    (0xf0 as f32 / 0xff as f32) as f32,
-    (0x94 as f32 / 0xff as f32) as f32,
-    (0x32 as f32 / 0xff as f32) as f32,
+    0xf0 as f32 / 0xff as f32,
+    0x94 as f32 / 0xff as f32,
+    0x32 as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const DARK_ORANGE: Color = Color::rgb(
-    (0xdc as f32 / 0xff as f32) as f32,
-    (0x53 as f32 / 0xff as f32) as f32,
-    (0x2d as f32 / 0xff as f32) as f32,
+    0xdc as f32 / 0xff as f32,
+    0x53 as f32 / 0xff as f32,
+    0x2d as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const LIME_GREEN: Color = Color::rgb(
-    (0xb4 as f32 / 0xff as f32) as f32,
-    (0xdc as f32 / 0xff as f32) as f32,
-    (0x25 as f32 / 0xff as f32) as f32,
+    0xb4 as f32 / 0xff as f32,
+    0xdc as f32 / 0xff as f32,
+    0x25 as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const PINK: Color = Color::rgb(
     0xff as f32,
-    (0x9c as f32 / 0xff as f32) as f32,
-    (0xc9 as f32 / 0xff as f32) as f32,
+    0x9c as f32 / 0xff as f32,
+    0xc9 as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const RED_PINK: Color = Color::rgb(
-    (0xf4 as f32 / 0xff as f32) as f32,
-    (0x36 as f32 / 0xff as f32) as f32,
-    (0x66 as f32 / 0xff as f32) as f32,
+    0xf4 as f32 / 0xff as f32,
+    0x36 as f32 / 0xff as f32,
+    0x66 as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const CRIMSON: Color = Color::rgb(
-    (0xa1 as f32 / 0xff as f32) as f32,
-    (0x25 as f32 / 0xff as f32) as f32,
-    (0x36 as f32 / 0xff as f32) as f32,
+    0xa1 as f32 / 0xff as f32,
+    0x25 as f32 / 0xff as f32,
+    0x36 as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const GREEEN: Color = Color::rgb(
-    (0x26 as f32 / 0xff as f32) as f32,
-    (0xa6 as f32 / 0xff as f32) as f32,
-    (0x30 as f32 / 0xff as f32) as f32,
+    0x26 as f32 / 0xff as f32,
+    0xa6 as f32 / 0xff as f32,
+    0x30 as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const LIGHT_GREEN: Color = Color::rgb(
-    (0x25 as f32 / 0xff as f32) as f32,
-    (0xe2 as f32 / 0xff as f32) as f32,
-    (0xc0 as f32 / 0xff as f32) as f32,
+    0x25 as f32 / 0xff as f32,
+    0xe2 as f32 / 0xff as f32,
+    0xc0 as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const MAGENTA: Color = Color::rgb(
-    (0xc6 as f32 / 0xff as f32) as f32,
-    (0x35 as f32 / 0xff as f32) as f32,
-    (0xbc as f32 / 0xff as f32) as f32,
+    0xc6 as f32 / 0xff as f32,
+    0x35 as f32 / 0xff as f32,
+    0xbc as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const PURPLE: Color = Color::rgb(
-    (0x6f as f32 / 0xff as f32) as f32,
-    (0x28 as f32 / 0xff as f32) as f32,
-    (0x8b as f32 / 0xff as f32) as f32,
+    0x6f as f32 / 0xff as f32,
+    0x28 as f32 / 0xff as f32,
+    0x8b as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const LIGHT_BLUE: Color = Color::rgb(
-    (0x5a as f32 / 0xff as f32) as f32,
-    (0xf0 as f32 / 0xff as f32) as f32,
-    (0xf7 as f32 / 0xff as f32) as f32,
+    0x5a as f32 / 0xff as f32,
+    0xf0 as f32 / 0xff as f32,
+    0xf7 as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const MEDIUM_BLUE: Color = Color::rgb(
-    (0x08 as f32 / 0xff as f32) as f32,
-    (0xa0 as f32 / 0xff as f32) as f32,
-    (0xc0 as f32 / 0xff as f32) as f32,
+    0x08 as f32 / 0xff as f32,
+    0xa0 as f32 / 0xff as f32,
+    0xc0 as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const BLUE: Color = Color::rgb(
-    (0x16 as f32 / 0xff as f32) as f32,
-    (0x5a as f32 / 0xff as f32) as f32,
-    (0x7d as f32 / 0xff as f32) as f32,
+    0x16 as f32 / 0xff as f32,
+    0x5a as f32 / 0xff as f32,
+    0x7d as f32 / 0xff as f32,
 );
 #[allow(dead_code)]
 pub const DARK_BLUE: Color = Color::rgb(
-    (0x26 as f32 / 0xff as f32) as f32,
-    (0x0e as f32 / 0xff as f32) as f32,
-    (0x3e as f32 / 0xff as f32) as f32,
+    0x26 as f32 / 0xff as f32,
+    0x0e as f32 / 0xff as f32,
+    0x3e as f32 / 0xff as f32,
 );
@@ -0,0 +1,436 @@
+use bevy::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::gold::{GoldPile, PileRemoveEvent};
+use crate::hex::{Blocked, Hex, HexCoords, HexSpawnEvent};
+use crate::tower::{Tower, TowerBuiltEvent, TowerPreview};
+
+// enemies can path this far from the center, on or off the board
+// reaches past where they spawn and where they escape
+pub const PATH_BOUND: u32 = 20;
+// cost of stepping onto open ground. Slow terrain costs more
+const STEP_COST: u32 = 10;
+
+pub struct PathfindingPlugin;
+
+impl Plugin for PathfindingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PathGrid::default())
+            .insert_resource(FlowField::default())
+            // removed components are only seen by later stages
+            .add_system_to_stage(CoreStage::PostUpdate, update_path_grid)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                update_flow_field.after(update_path_grid),
+            );
+    }
+}
+
+/// What enemies can walk through, and how slowly
+///
+/// Anything off the board is open ground.
+/// Towers and blocked hexes are walls.
+#[derive(Default)]
+pub struct PathGrid {
+    costs: HashMap<HexCoords, u32>,
+    walls: HashSet<HexCoords>,
+    // goes up every time the board changes
+    // so the flow field knows when it's out of date
+    pub version: u32,
+}
+
+impl PathGrid {
+    pub fn clear(&mut self) {
+        self.costs.clear();
+        self.walls.clear();
+        self.version += 1;
+    }
+
+    pub fn set_cost(&mut self, coords: HexCoords, cost: u32) {
+        self.costs.insert(coords, cost);
+    }
+
+    pub fn set_wall(&mut self, coords: HexCoords) {
+        self.walls.insert(coords);
+    }
+
+    /// Cost to step onto a hex. None if it can't be walked on
+    pub fn cost(&self, coords: HexCoords) -> Option<u32> {
+        if self.walls.contains(&coords) || coords.distance(HexCoords::new()) > PATH_BOUND {
+            return None;
+        }
+        Some(*self.costs.get(&coords).unwrap_or(&STEP_COST))
+    }
+
+    /// A* from start to goal
+    ///
+    /// Returns the hexes to walk through, not including start.
+    /// The goal can be a wall so enemies can still path to gold sitting on a tower.
+    // enemies follow the FlowField now. This is what it's checked against
+    #[cfg(test)]
+    pub fn find_path(&self, start: HexCoords, goal: HexCoords) -> Option<Vec<HexCoords>> {
+        if start == goal {
+            return Some(Vec::new());
+        }
+
+        // nodes are indexed by the order they were found
+        // so ties always break the same way
+        let mut nodes: Vec<HexCoords> = vec![start];
+        let mut open = BinaryHeap::new();
+        let mut best: HashMap<HexCoords, u32> = HashMap::new();
+        let mut came_from: HashMap<HexCoords, HexCoords> = HashMap::new();
+
+        let h = |c: HexCoords| c.distance(goal) * STEP_COST;
+        open.push(Reverse((h(start), h(start), 0usize)));
+        best.insert(start, 0);
+
+        while let Some(Reverse((_, _, i))) = open.pop() {
+            let current = nodes[i];
+            if current == goal {
+                let mut path = vec![goal];
+                let mut c = goal;
+                while let Some(&prev) = came_from.get(&c) {
+                    if prev == start {
+                        break;
+                    }
+                    path.push(prev);
+                    c = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let g = best[&current];
+            for n in current.get_neighbours() {
+                let step = if n == goal {
+                    self.cost(n).unwrap_or(STEP_COST)
+                } else {
+                    match self.cost(n) {
+                        Some(cost) => cost,
+                        None => continue,
+                    }
+                };
+
+                let new_g = g + step;
+                if best.get(&n).is_none_or(|&old| new_g < old) {
+                    best.insert(n, new_g);
+                    came_from.insert(n, current);
+                    nodes.push(n);
+                    open.push(Reverse((new_g + h(n), h(n), nodes.len() - 1)));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+fn update_path_grid(
+    mut grid: ResMut<PathGrid>,
+    q_hexes: Query<(
+        &Hex,
+        Option<&Blocked>,
+        Option<&Tower>,
+        Option<&TowerPreview>,
+    )>,
+    q_changed: Query<
+        (),
+        Or<(
+            Added<Hex>,
+            Added<Blocked>,
+            Added<Tower>,
+            Added<TowerPreview>,
+        )>,
+    >,
+    removed_towers: RemovedComponents<Tower>,
+    removed_previews: RemovedComponents<TowerPreview>,
+) {
+    if q_changed.is_empty()
+        && removed_towers.iter().next().is_none()
+        && removed_previews.iter().next().is_none()
+    {
+        return;
+    }
+
+    grid.clear();
+    for (hex, blocked, tower, preview) in q_hexes.iter() {
+        if blocked.is_some() || tower.is_some() || preview.is_some() {
+            grid.set_wall(hex.coords);
+        } else {
+            let cost = (STEP_COST as f32 / hex.terrain.enemy_speed()).round() as u32;
+            grid.set_cost(hex.coords, cost);
+        }
+    }
+}
+
+/// Cost from every hex to the nearest gold pile, and to the edge of the map
+///
+/// Shared by every enemy, so moving is one lookup each
+/// instead of a search each.
+#[derive(Default)]
+pub struct FlowField {
+    to_gold: HashMap<HexCoords, u32>,
+    to_edge: HashMap<HexCoords, u32>,
+    // grid version the fields were built from
+    version: u32,
+}
+
+impl FlowField {
+    pub fn rebuild(&mut self, grid: &PathGrid, gold: &[HexCoords]) {
+        self.to_gold.clear();
+        flood(grid, &mut self.to_gold, gold);
+
+        // fleeing enemies run for the edge of where they can path
+        self.to_edge.clear();
+        flood(
+            grid,
+            &mut self.to_edge,
+            &HexCoords::new().get_ring(PATH_BOUND),
+        );
+
+        self.version = grid.version;
+    }
+
+    /// A new pile only makes some hexes closer to gold
+    /// so there's no need to start over
+    pub fn add_gold(&mut self, grid: &PathGrid, coords: HexCoords) {
+        flood(grid, &mut self.to_gold, &[coords]);
+    }
+
+    /// Where to head next to get to the closest gold pile
+    ///
+    /// The pile's center once it's on the pile.
+    /// None if there are no piles or no way to reach one.
+    pub fn gold_step(&self, pos: Vec2) -> Option<Vec2> {
+        step(&self.to_gold, pos)
+    }
+
+    /// Where to head next to get off the map
+    pub fn edge_step(&self, pos: Vec2) -> Option<Vec2> {
+        step(&self.to_edge, pos)
+    }
+}
+
+// Dijkstra out from the sources, keeping any costs that are already lower
+// sources can be walls. Gold can sit on a tower preview
+fn flood(grid: &PathGrid, field: &mut HashMap<HexCoords, u32>, sources: &[HexCoords]) {
+    let mut open = BinaryHeap::new();
+    for &s in sources {
+        if field.get(&s).is_none_or(|&old| old > 0) {
+            field.insert(s, 0);
+            open.push(Reverse((0, <(isize, isize)>::from(s))));
+        }
+    }
+
+    // coords go on the heap as (u, v) so ties break the same way every time
+    while let Some(Reverse((d, uv))) = open.pop() {
+        let current = HexCoords::from(uv);
+        if field.get(&current).is_some_and(|&best| best < d) {
+            // already found a shorter way here
+            continue;
+        }
+
+        // cost for a neighbour to step onto this hex
+        let step = grid.cost(current).unwrap_or(STEP_COST);
+        for n in current.get_neighbours() {
+            if grid.cost(n).is_none() {
+                continue;
+            }
+            let new_d = d + step;
+            if field.get(&n).is_none_or(|&old| new_d < old) {
+                field.insert(n, new_d);
+                open.push(Reverse((new_d, <(isize, isize)>::from(n))));
+            }
+        }
+    }
+}
+
+fn step(field: &HashMap<HexCoords, u32>, pos: Vec2) -> Option<Vec2> {
+    let here = HexCoords::from_position(pos);
+    if field.get(&here) == Some(&0) {
+        return Some(here.to_position());
+    }
+
+    here.get_neighbours()
+        .into_iter()
+        .filter_map(|n| field.get(&n).map(|&d| (d, n)))
+        // first in ring order wins ties
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, n)| n.to_position())
+}
+
+fn update_flow_field(
+    mut field: ResMut<FlowField>,
+    grid: Res<PathGrid>,
+    q_piles: Query<&Hex, With<GoldPile>>,
+    q_new_piles: Query<&Hex, Added<GoldPile>>,
+    removed_piles: RemovedComponents<GoldPile>,
+    mut ev_built: EventReader<TowerBuiltEvent>,
+    mut ev_hex: EventReader<HexSpawnEvent>,
+    mut ev_pile_remove: EventReader<PileRemoveEvent>,
+) {
+    // piles are added and removed with commands
+    // so watch the component too. The events can show up a frame early
+    let rebuild = field.version != grid.version
+        || ev_built.iter().count() > 0
+        || ev_hex.iter().count() > 0
+        || ev_pile_remove.iter().count() > 0
+        || removed_piles.iter().next().is_some();
+
+    if rebuild {
+        let gold: Vec<HexCoords> = q_piles.iter().map(|hex| hex.coords).collect();
+        field.rebuild(&grid, &gold);
+    } else {
+        // PileSpawnEvent
+        for hex in q_new_piles.iter() {
+            field.add_gold(&grid, hex.coords);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn h(u: isize, v: isize) -> HexCoords {
+        HexCoords::from_axial(u, v)
+    }
+
+    fn assert_walkable(grid: &PathGrid, start: HexCoords, path: &[HexCoords]) {
+        let mut prev = start;
+        for &c in path {
+            assert_eq!(prev.distance(c), 1);
+            prev = c;
+        }
+        for &c in &path[..path.len() - 1] {
+            assert!(grid.cost(c).is_some(), "walked through {:?}", c);
+        }
+    }
+
+    #[test]
+    fn open_ground_is_a_straight_line() {
+        let grid = PathGrid::default();
+        let start = h(-3, 0);
+        let goal = h(4, -2);
+        let path = grid.find_path(start, goal).unwrap();
+        assert_eq!(path.len() as u32, start.distance(goal));
+        assert_eq!(*path.last().unwrap(), goal);
+        assert_walkable(&grid, start, &path);
+    }
+
+    #[test]
+    fn same_hex_is_empty() {
+        let grid = PathGrid::default();
+        assert_eq!(grid.find_path(h(1, 1), h(1, 1)), Some(Vec::new()));
+    }
+
+    #[test]
+    fn goes_around_walls() {
+        let mut grid = PathGrid::default();
+        // a wall across the middle with gaps at the ends
+        for u in -4..4 {
+            grid.set_wall(h(u, 0));
+        }
+        let start = h(0, -3);
+        let goal = h(0, 3);
+        let path = grid.find_path(start, goal).unwrap();
+        assert!(path.len() as u32 > start.distance(goal));
+        assert_walkable(&grid, start, &path);
+    }
+
+    #[test]
+    fn walled_in_has_no_path() {
+        let mut grid = PathGrid::default();
+        for c in h(0, 0).get_ring(2) {
+            grid.set_wall(c);
+        }
+        assert_eq!(grid.find_path(h(0, 0), h(5, 0)), None);
+        // the wall itself can still be reached from outside
+        assert!(grid.find_path(h(5, 0), h(2, 0)).is_some());
+    }
+
+    #[test]
+    fn goal_can_be_a_wall() {
+        let mut grid = PathGrid::default();
+        grid.set_wall(h(2, 0));
+        let path = grid.find_path(h(0, 0), h(2, 0)).unwrap();
+        assert_eq!(path, vec![h(1, 0), h(2, 0)]);
+    }
+
+    #[test]
+    fn avoids_slow_terrain() {
+        let mut grid = PathGrid::default();
+        // water straight ahead
+        grid.set_cost(h(1, 0), 100);
+        let path = grid.find_path(h(0, 0), h(2, 0)).unwrap();
+        assert_eq!(path.len(), 3);
+        assert!(!path.contains(&h(1, 0)));
+    }
+
+    fn walk(field: &HashMap<HexCoords, u32>, start: HexCoords) -> Vec<HexCoords> {
+        let mut path = vec![start];
+        while field.get(path.last().unwrap()) != Some(&0) {
+            let next = step(field, path.last().unwrap().to_position()).unwrap();
+            path.push(HexCoords::from_position(next));
+            assert!(path.len() < 100, "walked in circles");
+        }
+        path
+    }
+
+    #[test]
+    fn flow_field_goes_around_walls_to_gold() {
+        let mut grid = PathGrid::default();
+        for u in -4..4 {
+            grid.set_wall(h(u, 0));
+        }
+        let mut field = FlowField::default();
+        field.rebuild(&grid, &[h(0, 3)]);
+
+        let path = walk(&field.to_gold, h(0, -3));
+        assert_eq!(*path.last().unwrap(), h(0, 3));
+        assert_walkable(&grid, path[0], &path[1..]);
+        // same length as A* finds
+        let a_star = grid.find_path(h(0, -3), h(0, 3)).unwrap();
+        assert_eq!(path.len() - 1, a_star.len());
+    }
+
+    #[test]
+    fn flow_field_heads_for_the_closest_pile() {
+        let grid = PathGrid::default();
+        let mut field = FlowField::default();
+        field.rebuild(&grid, &[h(0, 0), h(6, 0)]);
+
+        let path = walk(&field.to_gold, h(4, 0));
+        assert_eq!(*path.last().unwrap(), h(6, 0));
+    }
+
+    #[test]
+    fn adding_gold_matches_a_rebuild() {
+        let mut grid = PathGrid::default();
+        grid.set_wall(h(2, 0));
+        grid.set_cost(h(-1, 1), 25);
+
+        let mut added = FlowField::default();
+        added.rebuild(&grid, &[h(0, 0)]);
+        added.add_gold(&grid, h(5, -2));
+
+        let mut rebuilt = FlowField::default();
+        rebuilt.rebuild(&grid, &[h(0, 0), h(5, -2)]);
+
+        assert_eq!(added.to_gold, rebuilt.to_gold);
+    }
+
+    #[test]
+    fn flow_field_without_gold() {
+        let grid = PathGrid::default();
+        let mut field = FlowField::default();
+        field.rebuild(&grid, &[]);
+        assert_eq!(field.gold_step(Vec2::ZERO), None);
+
+        // but there's always an edge
+        let pos = h(3, 0).to_position();
+        let next = field.edge_step(pos).unwrap();
+        assert!(next.length() > pos.length());
+    }
+}
@@ -0,0 +1,512 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::game_time::{Frame, GameTime};
+use crate::gold::{PileRemoveEvent, PileSpawnEvent};
+use crate::hex::{Hex, HexCoords, HexSpawnEvent};
+use crate::rng::{reset_rng, GameRng};
+use crate::tower::{PlaceTowerPreviewEvent, ShootType, SpawnBombBulletEvent, TowerRemoveEvent};
+use crate::waves::CallWaveEvent;
+use crate::{GameState, MouseWorldPos};
+
+/// Bump this when the format changes
+pub const REPLAY_VERSION: u32 = 2;
+const REPLAY_PATH: &str = "replays/last.ron";
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        // replaced with the real seed on entering the menu
+        app.insert_resource(Recording::new(0))
+            .init_resource::<Playback>()
+            .add_event::<PlayerCommand>()
+            // before the ticks, so they run the recorded number
+            .add_system_to_stage(CoreStage::PreUpdate, feed_frames)
+            .add_system(start_playback.before(apply_commands))
+            .add_system(apply_commands.after(PlayerInput))
+            .add_system(replay_input)
+            .add_system_set(
+                SystemSet::on_enter(GameState::Menu)
+                    .with_system(start_recording.after(reset_rng))
+                    .with_system(stop_playback),
+            );
+    }
+}
+
+/// Systems that turn the player's input into PlayerCommands
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct PlayerInput;
+
+/// Something the player did to the game
+///
+/// Input sends these instead of the game's own events,
+/// so everything the player does can be recorded and played back.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PlayerCommand {
+    PlaceTower {
+        coords: HexCoords,
+        shoot_type: ShootType,
+    },
+    RemoveTower {
+        coords: HexCoords,
+    },
+    SpawnPile {
+        coords: HexCoords,
+    },
+    RemovePile {
+        coords: HexCoords,
+    },
+    // the space bar's test bomb
+    Bomb {
+        target: (f32, f32),
+    },
+    // loose gold follows the mouse. Recorded whenever it moves
+    Mouse {
+        position: (f32, f32),
+    },
+    // the next wave, before its countdown is up
+    CallWave,
+}
+
+/// A whole game's worth of input
+///
+/// With the seed and the same map, playing it back gives the same game.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    // ticks of every frame the game was playing, run length encoded
+    pub frames: Vec<(Frame, u32)>,
+    pub commands: Vec<RecordedCommand>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecordedCommand {
+    // how many playing frames had gone by
+    pub frame: u32,
+    // only to tell when a replay has gone out of sync
+    pub tick: u64,
+    pub command: PlayerCommand,
+}
+
+// read on its own first so an old replay gets a clear error
+#[derive(Deserialize)]
+struct ReplayVersion {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Parse(ron::Error),
+    Version(u32),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "couldn't read the replay: {e}"),
+            ReplayError::Parse(e) => write!(f, "couldn't parse the replay: {e}"),
+            ReplayError::Version(v) => {
+                write!(f, "replay is version {v}, expected {REPLAY_VERSION}")
+            }
+        }
+    }
+}
+
+impl Replay {
+    pub fn new(seed: u64) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            seed,
+            frames: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn push_frame(&mut self, frame: Frame) {
+        match self.frames.last_mut() {
+            Some((last, count)) if *last == frame => *count += 1,
+            _ => self.frames.push((frame, 1)),
+        }
+    }
+
+    pub fn push(&mut self, command: RecordedCommand) {
+        // only where the mouse ended up matters to the next tick
+        if let Some(last) = self.commands.last_mut() {
+            let both_mouse = matches!(last.command, PlayerCommand::Mouse { .. })
+                && matches!(command.command, PlayerCommand::Mouse { .. });
+            if both_mouse && last.frame == command.frame {
+                *last = command;
+                return;
+            }
+        }
+        self.commands.push(command);
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = Frame> + '_ {
+        self.frames
+            .iter()
+            .flat_map(|&(frame, count)| std::iter::repeat_n(frame, count as usize))
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, ReplayError> {
+        let version: ReplayVersion = ron::from_str(text).map_err(ReplayError::Parse)?;
+        if version.version != REPLAY_VERSION {
+            return Err(ReplayError::Version(version.version));
+        }
+        ron::from_str(text).map_err(ReplayError::Parse)
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let text = self.to_ron().map_err(std::io::Error::other)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, ReplayError> {
+        let text = fs::read_to_string(path).map_err(ReplayError::Io)?;
+        Replay::from_ron(&text)
+    }
+}
+
+/// Reads the replay named by `--replay PATH` on the command line
+pub fn replay_arg(args: &[String]) -> Result<Option<Replay>, String> {
+    match args.iter().position(|a| a == "--replay") {
+        Some(i) => {
+            let path = args.get(i + 1).ok_or("--replay needs a path")?;
+            Replay::read(Path::new(path))
+                .map(Some)
+                .map_err(|e| format!("{path}: {e}"))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The game being played, kept as a replay
+pub struct Recording {
+    // None for a game loaded from a save. It can't be played from the start
+    pub replay: Option<Replay>,
+    // playing frames so far
+    frames: u32,
+    // where the last Mouse command put it
+    mouse: Option<Vec2>,
+}
+
+impl Recording {
+    pub fn new(seed: u64) -> Self {
+        Recording {
+            replay: Some(Replay::new(seed)),
+            frames: 0,
+            mouse: None,
+        }
+    }
+
+    fn record(&mut self, tick: u64, command: PlayerCommand) {
+        if let PlayerCommand::Mouse { position } = command {
+            self.mouse = Some(position.into());
+        }
+        let frame = self.frames;
+        if let Some(replay) = &mut self.replay {
+            replay.push(RecordedCommand {
+                frame,
+                tick,
+                command,
+            });
+        }
+    }
+}
+
+/// A replay playing in place of the player
+#[derive(Default)]
+pub struct Playback {
+    replay: Option<Replay>,
+    frames: Vec<Frame>,
+    // frames run so far
+    frame: usize,
+    // commands applied so far
+    next: usize,
+    mouse: Option<Vec2>,
+    // waits for the board to be laid out
+    started: bool,
+    desynced: bool,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Playback {
+            frames: replay.frames().collect(),
+            replay: Some(replay),
+            ..default()
+        }
+    }
+
+    /// The player's input is ignored while this is true
+    pub fn playing(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    // everything that was recorded by this many frames
+    fn due(&mut self, tick: u64) -> Vec<PlayerCommand> {
+        let mut due = Vec::new();
+        let replay = match &self.replay {
+            Some(replay) => replay,
+            None => return due,
+        };
+        while let Some(recorded) = replay.commands.get(self.next) {
+            if recorded.frame as usize > self.frame {
+                break;
+            }
+            if recorded.tick != tick && !self.desynced {
+                // the game's changed since it was recorded. It'll only get worse
+                warn!(
+                    "Replay out of sync at frame {}. Tick {tick}, recorded at {}",
+                    self.frame, recorded.tick
+                );
+                self.desynced = true;
+            }
+            due.push(recorded.command.clone());
+            self.next += 1;
+        }
+        due
+    }
+
+    fn finished(&self) -> bool {
+        let commands = self.replay.as_ref().map_or(0, |r| r.commands.len());
+        self.started && self.frame >= self.frames.len() && self.next >= commands
+    }
+}
+
+// a new game gets a new recording
+fn start_recording(rng: Res<GameRng>, mut recording: ResMut<Recording>) {
+    *recording = Recording::new(rng.seed());
+}
+
+// restarting hands the game back to the player
+fn stop_playback(mut playback: ResMut<Playback>) {
+    if playback.started {
+        *playback = Playback::default();
+    }
+}
+
+// once the board is set out, play from the replay's seed
+fn start_playback(
+    mut playback: ResMut<Playback>,
+    mut recording: ResMut<Recording>,
+    mut rng: ResMut<GameRng>,
+    mut state: ResMut<State<GameState>>,
+    q_hexes: Query<(), With<Hex>>,
+    ev_hexes: Res<Events<HexSpawnEvent>>,
+    ev_piles: Res<Events<PileSpawnEvent>>,
+) {
+    let seed = match &playback.replay {
+        Some(replay) if !playback.started => replay.seed,
+        _ => return,
+    };
+    // piles go down a frame or two after their hexes
+    if q_hexes.is_empty() || !ev_hexes.is_empty() || !ev_piles.is_empty() {
+        return;
+    }
+
+    info!("Playing a replay. Game seed: {seed}");
+    rng.reseed(seed);
+    *recording = Recording::new(seed);
+    playback.started = true;
+    if *state.current() == GameState::Menu {
+        let _ = state.set(GameState::Playing);
+    }
+}
+
+// the recorded frame instead of the clock
+fn feed_frames(
+    state: Res<State<GameState>>,
+    mut playback: ResMut<Playback>,
+    mut game_time: ResMut<GameTime>,
+) {
+    if !playback.playing() || *state.current() != GameState::Playing {
+        return;
+    }
+    if !playback.started {
+        // nothing moves until the board is set out
+        let speed = game_time.speed;
+        game_time.play_frame(Frame { ticks: 0, speed });
+        return;
+    }
+    if let Some(&frame) = playback.frames.get(playback.frame) {
+        playback.frame += 1;
+        game_time.play_frame(frame);
+    }
+}
+
+/// Records the player's commands, or the replay's, and carries them out
+pub fn apply_commands(
+    mut playback: ResMut<Playback>,
+    mut recording: ResMut<Recording>,
+    game_time: Res<GameTime>,
+    mut mouse: ResMut<MouseWorldPos>,
+    mut ev_commands: EventReader<PlayerCommand>,
+    mut ev_place: EventWriter<PlaceTowerPreviewEvent>,
+    mut ev_remove_tower: EventWriter<TowerRemoveEvent>,
+    mut ev_pile_spawn: EventWriter<PileSpawnEvent>,
+    mut ev_pile_remove: EventWriter<PileRemoveEvent>,
+    mut ev_bomb: EventWriter<SpawnBombBulletEvent>,
+    mut ev_call_wave: EventWriter<CallWaveEvent>,
+) {
+    if let Some(frame) = game_time.frame() {
+        recording.frames += 1;
+        if let Some(replay) = &mut recording.replay {
+            replay.push_frame(frame);
+        }
+    }
+
+    let commands = if playback.playing() {
+        // the player only watches
+        ev_commands.clear();
+        playback.due(game_time.ticks())
+    } else {
+        let mut commands: Vec<PlayerCommand> = ev_commands.iter().cloned().collect();
+        if recording.mouse != Some(mouse.0) {
+            commands.push(PlayerCommand::Mouse {
+                position: mouse.0.into(),
+            });
+        }
+        commands
+    };
+
+    for command in commands {
+        recording.record(game_time.ticks(), command.clone());
+        match command {
+            PlayerCommand::PlaceTower { coords, shoot_type } => {
+                ev_place.send(PlaceTowerPreviewEvent { coords, shoot_type })
+            }
+            PlayerCommand::RemoveTower { coords } => {
+                ev_remove_tower.send(TowerRemoveEvent { coords })
+            }
+            PlayerCommand::SpawnPile { coords } => ev_pile_spawn.send(PileSpawnEvent::new(coords)),
+            PlayerCommand::RemovePile { coords } => ev_pile_remove.send(PileRemoveEvent { coords }),
+            PlayerCommand::Bomb { target } => ev_bomb.send(SpawnBombBulletEvent {
+                start_pos: Vec2::ZERO,
+                target_dir: target.into(),
+                damage: ShootType::Arc.damage(),
+                status: ShootType::Arc.status(),
+            }),
+            PlayerCommand::Mouse { position } => playback.mouse = Some(position.into()),
+            PlayerCommand::CallWave => ev_call_wave.send(CallWaveEvent),
+        }
+    }
+
+    if playback.playing() {
+        // the real cursor doesn't get a say
+        if let Some(position) = playback.mouse {
+            mouse.0 = position;
+        }
+        if playback.finished() {
+            info!("Replay finished");
+            *playback = Playback::default();
+        }
+    }
+}
+
+// F8 keeps a replay of the game so far
+fn replay_input(keys: Res<Input<KeyCode>>, recording: Res<Recording>) {
+    if !keys.just_pressed(KeyCode::F8) {
+        return;
+    }
+    match &recording.replay {
+        Some(replay) => match replay.write(Path::new(REPLAY_PATH)) {
+            Ok(()) => info!("Replay saved to {REPLAY_PATH}"),
+            Err(e) => error!("Couldn't save the replay: {e}"),
+        },
+        None => warn!("A game loaded from a save can't be replayed"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mouse(frame: u32, x: f32) -> RecordedCommand {
+        RecordedCommand {
+            frame,
+            tick: frame as u64,
+            command: PlayerCommand::Mouse { position: (x, 0.5) },
+        }
+    }
+
+    fn sample() -> Replay {
+        let mut replay = Replay::new(42);
+        for ticks in [1, 1, 1, 0, 2, 1] {
+            replay.push_frame(Frame { ticks, speed: 1.0 });
+        }
+        replay.push_frame(Frame {
+            ticks: 4,
+            speed: 4.0,
+        });
+        replay.push(RecordedCommand {
+            frame: 2,
+            tick: 2,
+            command: PlayerCommand::PlaceTower {
+                coords: HexCoords::from((1, -1)),
+                shoot_type: ShootType::Arc,
+            },
+        });
+        replay.push(mouse(3, 10.25));
+        replay.push(mouse(3, 11.0));
+        replay.push(mouse(4, 12.0));
+        replay
+    }
+
+    #[test]
+    fn packs_frames_and_mouse_moves() {
+        let replay = sample();
+        assert_eq!(replay.frames.len(), 5);
+        let ticks: Vec<u32> = replay.frames().map(|f| f.ticks).collect();
+        assert_eq!(ticks, vec![1, 1, 1, 0, 2, 1, 4]);
+        // only the last move in a frame is kept
+        assert_eq!(replay.commands.len(), 3);
+        assert_eq!(replay.commands[1], mouse(3, 11.0));
+    }
+
+    #[test]
+    fn round_trip_through_ron() {
+        let replay = sample();
+        let text = replay.to_ron().unwrap();
+        assert_eq!(Replay::from_ron(&text).unwrap(), replay);
+
+        let mut old = sample();
+        old.version = REPLAY_VERSION + 1;
+        assert!(matches!(
+            Replay::from_ron(&old.to_ron().unwrap()),
+            Err(ReplayError::Version(v)) if v == REPLAY_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn commands_come_back_on_their_frame() {
+        let mut playback = Playback::new(sample());
+        playback.started = true;
+        assert!(playback.due(0).is_empty());
+        playback.frame = 2;
+        assert!(matches!(
+            playback.due(2)[..],
+            [PlayerCommand::PlaceTower { .. }]
+        ));
+        // a frame late, but in order
+        playback.frame = 4;
+        assert_eq!(playback.due(3).len(), 2);
+        assert!(playback.desynced);
+        assert!(!playback.finished());
+        playback.frame = 7;
+        assert!(playback.finished());
+    }
+}
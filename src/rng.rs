@@ -0,0 +1,144 @@
+use bevy::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use crate::GameState;
+
+// every subsystem gets its own stream of the same seed
+// never reuse a number, or two subsystems get the same rolls
+const ENEMY_STREAM: u64 = 1;
+const BOIDS_STREAM: u64 = 2;
+const BOT_STREAM: u64 = 3;
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        // main and the simulation insert their own when given a seed
+        app.init_resource::<GameRng>()
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(reset_rng));
+    }
+}
+
+/// Random numbers for gameplay
+///
+/// Seeded, so a game can be played again with the same seed.
+/// Rolling more in one stream doesn't shift the others.
+pub struct GameRng {
+    seed: u64,
+    // given on the command line. Every new game uses it
+    fixed: bool,
+    pub enemies: ChaCha8Rng,
+    pub boids: ChaCha8Rng,
+    // the simulation's pretend player
+    pub bot: ChaCha8Rng,
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        let mut rng = GameRng::new(random());
+        rng.fixed = false;
+        rng
+    }
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            fixed: true,
+            enemies: stream(seed, ENEMY_STREAM),
+            boids: stream(seed, BOIDS_STREAM),
+            bot: stream(seed, BOT_STREAM),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Starts every stream over from this seed
+    pub fn reseed(&mut self, seed: u64) {
+        let fixed = self.fixed;
+        *self = GameRng::new(seed);
+        self.fixed = fixed;
+    }
+}
+
+// one seed, but a stream each, so drawing more from one doesn't shift the rest.
+// A seed replays the same on the same rand_chacha version. Sample ranges
+// over a length as u32, since usize is smaller on wasm
+fn stream(seed: u64, stream: u64) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(stream);
+    rng
+}
+
+/// Reads `--seed N` out of the command line
+pub fn seed_arg(args: &[String]) -> Result<Option<u64>, String> {
+    match args.iter().position(|a| a == "--seed") {
+        Some(i) => {
+            let value = args.get(i + 1).ok_or("--seed needs a value")?;
+            value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("bad value for --seed: {value}"))
+        }
+        None => Ok(None),
+    }
+}
+
+// a new game starts the streams over
+// from a new seed, unless one was picked
+pub fn reset_rng(mut rng: ResMut<GameRng>) {
+    let seed = if rng.fixed { rng.seed } else { random() };
+    rng.reseed(seed);
+    // run with --seed to play this game again
+    info!("Game seed: {seed}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_rolls() {
+        let mut a = GameRng::new(42);
+        let mut b = GameRng::new(42);
+        let rolls_a: Vec<u32> = (0..10).map(|_| a.enemies.gen()).collect();
+        let rolls_b: Vec<u32> = (0..10).map(|_| b.enemies.gen()).collect();
+        assert_eq!(rolls_a, rolls_b);
+
+        let mut c = GameRng::new(43);
+        let rolls_c: Vec<u32> = (0..10).map(|_| c.enemies.gen()).collect();
+        assert_ne!(rolls_a, rolls_c);
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        let mut quiet = GameRng::new(7);
+        let mut busy = GameRng::new(7);
+        // lots of boid jitter in one game only
+        for _ in 0..100 {
+            let _: f32 = busy.boids.gen();
+        }
+        let a: Vec<f32> = (0..10).map(|_| quiet.enemies.gen()).collect();
+        let b: Vec<f32> = (0..10).map(|_| busy.enemies.gen()).collect();
+        assert_eq!(a, b);
+
+        // and the streams aren't copies of each other
+        let mut rng = GameRng::new(7);
+        let enemies: Vec<u32> = (0..10).map(|_| rng.enemies.gen()).collect();
+        let boids: Vec<u32> = (0..10).map(|_| rng.boids.gen()).collect();
+        assert_ne!(enemies, boids);
+    }
+
+    #[test]
+    fn reads_the_seed_arg() {
+        let args = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+        assert_eq!(seed_arg(&args("--seed 12")), Ok(Some(12)));
+        assert_eq!(seed_arg(&args("")), Ok(None));
+        assert!(seed_arg(&args("--seed")).is_err());
+        assert!(seed_arg(&args("--seed abc")).is_err());
+    }
+}
@@ -0,0 +1,872 @@
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::enemies::{spawn_boss_at, spawn_enemy_at, Boss, Dead, Enemy, Health, BOSS_DEFEAT_TIME};
+use crate::enemy_types::EnemyTypes;
+use crate::game_assets::GameAssets;
+use crate::gold::{carry_gold, insert_pile, Gold, GoldPile, GoldSpawner, SpawnGoldEvent};
+use crate::hex::{Blocked, Hex, HexCollection, HexCoords, HexGrowth, HexSpawnEvent};
+use crate::lives::Lives;
+use crate::map::{spawn_map, CurrentMap, HexDefaults};
+use crate::replay::Recording;
+use crate::status::{StatusEffects, StatusHit};
+use crate::terrain::Terrain;
+use crate::tower::{
+    insert_preview, insert_tower, BombTower, FrostTower, ShootType, Tower, TowerCount,
+    TowerPreview, TowerSpawnCost,
+};
+use crate::waves::{QueuedSpawn, Waves};
+use crate::GameState;
+
+/// Bump this when the format changes
+/// Saves from other versions are refused instead of half loaded
+pub const SAVE_VERSION: u32 = 3;
+const SAVE_PATH: &str = "saves/quicksave.ron";
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PendingLoad::default())
+            .add_system(save_input.exclusive_system())
+            .add_system(load_input)
+            .add_system_set(
+                SystemSet::on_update(GameState::Menu).with_system(start_load.before(spawn_map)),
+            )
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(finish_load));
+    }
+}
+
+/// Everything needed to pick a game back up
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SaveGame {
+    pub version: u32,
+    pub hexes: Vec<SavedHex>,
+    pub growth: Option<SavedGrowth>,
+    pub piles: Vec<SavedPile>,
+    pub previews: Vec<SavedPreview>,
+    pub towers: Vec<SavedTower>,
+    // loose gold lying on the ground
+    pub gold: Vec<(f32, f32)>,
+    pub enemies: Vec<SavedEnemy>,
+    pub boss: Option<SavedBoss>,
+    pub waves: SavedWaves,
+    pub tower_cost: u32,
+    pub tower_count: u32,
+    pub boss_spawned: bool,
+    pub lives: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedHex {
+    pub coords: HexCoords,
+    pub terrain: Terrain,
+    pub gold: u32,
+    pub max_gold: u32,
+    pub regen_time: f32,
+    pub regen_elapsed: f32,
+    pub blocked: bool,
+}
+
+/// The rings still to come on a growing map
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedGrowth {
+    pub radius: u32,
+    pub max_radius: u32,
+    pub ring_time: f32,
+    pub ring: Vec<HexCoords>,
+    pub step_time: f32,
+    pub elapsed: f32,
+    pub defaults: HexDefaults,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedPile {
+    pub coords: HexCoords,
+    pub count: u32,
+    pub cap: u32,
+}
+
+/// A tower that's still being paid for
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedPreview {
+    pub coords: HexCoords,
+    pub kind: ShootType,
+    pub count: u32,
+    pub cap: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedTower {
+    pub coords: HexCoords,
+    pub kind: ShootType,
+    pub refund: u32,
+    pub cooldown_elapsed: f32,
+    pub can_shoot: bool,
+    pub mining_elapsed: f32,
+}
+
+/// Where the game is in the wave script
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedWaves {
+    pub started: u32,
+    // the countdown to the next wave
+    pub countdown: f32,
+    pub countdown_elapsed: f32,
+    // enemies from started waves that haven't come in yet
+    pub queue: Vec<QueuedSpawn>,
+    pub current: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedEnemy {
+    pub position: (f32, f32),
+    pub has_gold: bool,
+    // None for an enemy that hasn't been hurt
+    pub health: Option<u32>,
+    // an EnemyType id
+    pub kind: String,
+    // gold it's running off with, when that's more than one
+    pub carrying: Option<u32>,
+    // slows, burns and the like, with the seconds they have left
+    pub status: Vec<StatusHit>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedBoss {
+    pub position: (f32, f32),
+    pub count: u32,
+    pub cap: u32,
+    // phases it's been through
+    pub phase: usize,
+    // how far into being beaten. None while it's still going
+    pub defeat_elapsed: Option<f32>,
+}
+
+// read on its own first so an old save gets a clear error
+#[derive(Deserialize)]
+struct SaveVersion {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Parse(ron::Error),
+    Version(u32),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "couldn't read the save: {e}"),
+            LoadError::Parse(e) => write!(f, "couldn't parse the save: {e}"),
+            LoadError::Version(v) => {
+                write!(f, "save is version {v}, expected {SAVE_VERSION}")
+            }
+        }
+    }
+}
+
+impl SaveGame {
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, LoadError> {
+        let version: SaveVersion = ron::from_str(text).map_err(LoadError::Parse)?;
+        if version.version != SAVE_VERSION {
+            return Err(LoadError::Version(version.version));
+        }
+        ron::from_str(text).map_err(LoadError::Parse)
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let text = self.to_ron().map_err(std::io::Error::other)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, LoadError> {
+        let text = fs::read_to_string(path).map_err(LoadError::Io)?;
+        SaveGame::from_ron(&text)
+    }
+
+    /// Takes a snapshot of the running game
+    pub fn collect(world: &mut World) -> Self {
+        let mut hexes: Vec<SavedHex> = world
+            .query::<(&Hex, Option<&Blocked>)>()
+            .iter(world)
+            .map(|(hex, blocked)| SavedHex {
+                coords: hex.coords,
+                terrain: hex.terrain,
+                gold: hex.gold,
+                max_gold: hex.max_gold,
+                regen_time: hex.timer.duration().as_secs_f32(),
+                regen_elapsed: hex.timer.elapsed_secs(),
+                blocked: blocked.is_some(),
+            })
+            .collect();
+        // the collection is a hashmap. Keep saves of the same game the same
+        hexes.sort_by_key(|h| <(isize, isize)>::from(h.coords));
+
+        let mut piles: Vec<SavedPile> = world
+            .query_filtered::<(&Hex, &GoldPile), (Without<TowerPreview>, Without<Tower>)>()
+            .iter(world)
+            .map(|(hex, pile)| SavedPile {
+                coords: hex.coords,
+                count: pile.count,
+                cap: pile.gold_cap,
+            })
+            .collect();
+        piles.sort_by_key(|p| <(isize, isize)>::from(p.coords));
+
+        let mut previews: Vec<SavedPreview> = world
+            .query_filtered::<
+                (&Hex, &GoldPile, Option<&BombTower>, Option<&FrostTower>),
+                With<TowerPreview>,
+            >()
+            .iter(world)
+            .map(|(hex, pile, bomb, frost)| SavedPreview {
+                coords: hex.coords,
+                kind: match (bomb, frost) {
+                    (Some(_), _) => ShootType::Arc,
+                    (_, Some(_)) => ShootType::Frost,
+                    _ => ShootType::Bullet,
+                },
+                count: pile.count,
+                cap: pile.gold_cap,
+            })
+            .collect();
+        previews.sort_by_key(|p| <(isize, isize)>::from(p.coords));
+
+        let mut towers: Vec<SavedTower> = world
+            .query::<(&Tower, Option<&GoldSpawner>)>()
+            .iter(world)
+            .map(|(tower, spawner)| SavedTower {
+                coords: tower.coords,
+                kind: tower.shoot_type,
+                refund: tower.refund,
+                cooldown_elapsed: tower.shoot_timer.elapsed_secs(),
+                can_shoot: tower.can_shoot,
+                mining_elapsed: spawner.map_or(0.0, |s| s.timer.elapsed_secs()),
+            })
+            .collect();
+        towers.sort_by_key(|t| <(isize, isize)>::from(t.coords));
+
+        let gold = world
+            .query_filtered::<&Transform, With<Gold>>()
+            .iter(world)
+            .map(|t| (t.translation.x, t.translation.y))
+            .collect();
+
+        let enemies = world
+            .query_filtered::<(
+                &Transform,
+                &Enemy,
+                Option<&Health>,
+                Option<&Children>,
+                Option<&StatusEffects>,
+            ), Without<Dead>>()
+            .iter(world)
+            .map(|(t, enemy, health, children, effects)| SavedEnemy {
+                position: (t.translation.x, t.translation.y),
+                has_gold: enemy.has_gold,
+                health: health.filter(|h| h.current < h.max).map(|h| h.current),
+                kind: enemy.kind.clone(),
+                // everything an enemy holds is gold
+                carrying: children.map(|c| c.len() as u32).filter(|&n| n > 1),
+                status: effects.map_or(Vec::new(), |e| e.active().collect()),
+            })
+            .collect();
+
+        let boss = world
+            .query::<(&Transform, &GoldPile, &Boss)>()
+            .iter(world)
+            .next()
+            .map(|(t, pile, boss)| SavedBoss {
+                position: (t.translation.x, t.translation.y),
+                count: pile.count,
+                cap: pile.gold_cap,
+                phase: boss.phase,
+                defeat_elapsed: boss.defeat.as_ref().map(|t| t.elapsed_secs()),
+            });
+
+        let waves = world.resource::<Waves>();
+        let waves = SavedWaves {
+            started: waves.started,
+            countdown: waves.countdown.duration().as_secs_f32(),
+            countdown_elapsed: waves.countdown.elapsed_secs(),
+            queue: waves.queue.clone(),
+            current: waves.current,
+        };
+
+        let growth = world.resource::<HexGrowth>();
+        let growth = SavedGrowth {
+            radius: growth.radius,
+            max_radius: growth.max_radius,
+            ring_time: growth.ring_time,
+            ring: growth.ring.clone(),
+            step_time: growth.timer.duration().as_secs_f32(),
+            elapsed: growth.timer.elapsed_secs(),
+            defaults: growth.defaults.clone(),
+        };
+
+        let tower_count = world.resource::<TowerCount>();
+
+        SaveGame {
+            version: SAVE_VERSION,
+            hexes,
+            growth: Some(growth),
+            piles,
+            previews,
+            towers,
+            gold,
+            enemies,
+            boss,
+            waves,
+            tower_cost: world.resource::<TowerSpawnCost>().cost,
+            tower_count: tower_count.count,
+            boss_spawned: tower_count.boss_spawned,
+            lives: world.resource::<Lives>().count,
+        }
+    }
+}
+
+/// A save that's been read but isn't on the board yet
+#[derive(Default)]
+struct PendingLoad {
+    save: Option<SaveGame>,
+    // the hexes have been asked for
+    board_sent: bool,
+}
+
+// F5 saves the game in progress
+fn save_input(world: &mut World) {
+    if !world.resource::<Input<KeyCode>>().just_pressed(KeyCode::F5) {
+        return;
+    }
+    let state = *world.resource::<State<GameState>>().current();
+    if state != GameState::Playing && state != GameState::Paused {
+        return;
+    }
+
+    let save = SaveGame::collect(world);
+    match save.write(Path::new(SAVE_PATH)) {
+        Ok(()) => info!("Saved to {SAVE_PATH}"),
+        Err(e) => error!("Couldn't save: {e}"),
+    }
+}
+
+// F9 throws away the current game and loads the save
+fn load_input(
+    keys: Res<Input<KeyCode>>,
+    mut pending: ResMut<PendingLoad>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if !keys.just_pressed(KeyCode::F9) || pending.save.is_some() {
+        return;
+    }
+
+    match SaveGame::read(Path::new(SAVE_PATH)) {
+        Ok(save) => {
+            pending.save = Some(save);
+            pending.board_sent = false;
+            // going back to the menu clears out the old game
+            if *state.current() != GameState::Menu {
+                let _ = state.replace(GameState::Menu);
+            }
+        }
+        Err(e) => error!("Couldn't load: {e}"),
+    }
+}
+
+// lay out the saved board instead of the map
+fn start_load(
+    mut commands: Commands,
+    mut pending: ResMut<PendingLoad>,
+    mut state: ResMut<State<GameState>>,
+    mut current: ResMut<CurrentMap>,
+    mut hex_collect: ResMut<HexCollection>,
+    mut growth: ResMut<HexGrowth>,
+    mut cost: ResMut<TowerSpawnCost>,
+    mut tower_count: ResMut<TowerCount>,
+    mut waves: ResMut<Waves>,
+    mut lives: ResMut<Lives>,
+    mut recording: ResMut<Recording>,
+    q_hexes: Query<Entity, With<Hex>>,
+    mut ev_spawn: EventWriter<HexSpawnEvent>,
+) {
+    if pending.board_sent {
+        return;
+    }
+    let save = match &pending.save {
+        Some(save) => save,
+        None => return,
+    };
+
+    // the menu shows the map's board
+    for ent in q_hexes.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+    hex_collect.hexes.clear();
+    current.spawned = true;
+
+    ev_spawn.send_batch(save.hexes.iter().map(|h| HexSpawnEvent {
+        coords: h.coords,
+        terrain: h.terrain,
+        gold: h.gold,
+        max_gold: h.max_gold,
+        regen_time: h.regen_time,
+        blocked: h.blocked,
+    }));
+
+    *growth = HexGrowth::none();
+    if let Some(saved) = &save.growth {
+        growth.radius = saved.radius;
+        growth.max_radius = saved.max_radius;
+        growth.ring_time = saved.ring_time;
+        growth.ring = saved.ring.clone();
+        growth.defaults = saved.defaults.clone();
+        growth.timer = Timer::from_seconds(saved.step_time, true);
+        growth
+            .timer
+            .set_elapsed(Duration::from_secs_f32(saved.elapsed));
+    }
+
+    cost.cost = save.tower_cost;
+    tower_count.count = save.tower_count;
+    tower_count.boss_spawned = save.boss_spawned;
+    waves.started = save.waves.started;
+    waves.countdown = Timer::from_seconds(save.waves.countdown, false);
+    waves
+        .countdown
+        .set_elapsed(Duration::from_secs_f32(save.waves.countdown_elapsed));
+    waves.queue = save.waves.queue.clone();
+    waves.current = save.waves.current;
+    lives.count = save.lives;
+    // a replay only knows how to start from the map
+    recording.replay = None;
+
+    pending.board_sent = true;
+    let _ = state.set(GameState::Playing);
+}
+
+// everything else goes on once the hexes exist
+fn finish_load(
+    mut commands: Commands,
+    mut pending: ResMut<PendingLoad>,
+    hex_collect: Res<HexCollection>,
+    mut q_hexes: Query<&mut Hex>,
+    game_assets: Res<GameAssets>,
+    types: Res<EnemyTypes>,
+    mut ev_spawn_gold: EventWriter<SpawnGoldEvent>,
+) {
+    if !pending.board_sent {
+        return;
+    }
+    let save = match &pending.save {
+        Some(save) => save,
+        None => return,
+    };
+
+    let ready = save.hexes.iter().all(|h| {
+        hex_collect
+            .hexes
+            .get(&h.coords)
+            .is_some_and(|&e| q_hexes.get(e).is_ok())
+    });
+    if !ready {
+        return;
+    }
+
+    for h in save.hexes.iter() {
+        if let Ok(mut hex) = q_hexes.get_mut(hex_collect.hexes[&h.coords]) {
+            hex.timer
+                .set_elapsed(Duration::from_secs_f32(h.regen_elapsed));
+        }
+    }
+
+    for p in save.piles.iter() {
+        insert_pile(
+            &mut commands,
+            hex_collect.hexes[&p.coords],
+            GoldPile {
+                count: p.count,
+                gold_cap: p.cap,
+            },
+        );
+    }
+
+    for p in save.previews.iter() {
+        insert_preview(
+            &mut commands,
+            hex_collect.hexes[&p.coords],
+            GoldPile {
+                count: p.count,
+                gold_cap: p.cap,
+            },
+            p.kind,
+            &game_assets,
+        );
+    }
+
+    for t in save.towers.iter() {
+        let mut tower = Tower::new(t.coords, t.refund);
+        tower.shoot_type = t.kind;
+        tower.can_shoot = t.can_shoot;
+        tower
+            .shoot_timer
+            .set_elapsed(Duration::from_secs_f32(t.cooldown_elapsed));
+        let mut spawner = GoldSpawner::new();
+        spawner
+            .timer
+            .set_elapsed(Duration::from_secs_f32(t.mining_elapsed));
+        insert_tower(
+            &mut commands,
+            hex_collect.hexes[&t.coords],
+            tower,
+            spawner,
+            &game_assets,
+        );
+    }
+
+    ev_spawn_gold.send_batch(save.gold.iter().map(|&(x, y)| SpawnGoldEvent {
+        position: Vec3::new(x, y, 0.3),
+    }));
+
+    for e in save.enemies.iter() {
+        let kind = match types.get(&e.kind) {
+            Some(kind) => kind,
+            None => {
+                warn!("no enemy type called {:?}", e.kind);
+                continue;
+            }
+        };
+        let ent = spawn_enemy_at(
+            &mut commands,
+            Vec3::new(e.position.0, e.position.1, 0.3),
+            kind,
+        );
+        if e.has_gold {
+            commands.entity(ent).insert(Enemy {
+                has_gold: true,
+                ..Enemy::new(kind)
+            });
+            for _ in 0..e.carrying.unwrap_or(1) {
+                carry_gold(&mut commands, ent, &game_assets);
+            }
+        }
+        if let Some(current) = e.health {
+            commands.entity(ent).insert(Health {
+                current,
+                max: kind.health,
+            });
+        }
+        if !e.status.is_empty() {
+            let mut effects = StatusEffects::default();
+            for &hit in e.status.iter() {
+                effects.apply(hit);
+            }
+            commands.entity(ent).insert(effects);
+        }
+    }
+
+    if let Some(boss) = &save.boss {
+        let defeat = boss.defeat_elapsed.map(|elapsed| {
+            let mut timer = Timer::from_seconds(BOSS_DEFEAT_TIME, false);
+            timer.set_elapsed(Duration::from_secs_f32(elapsed));
+            timer
+        });
+        spawn_boss_at(
+            &mut commands,
+            &game_assets,
+            Vec3::new(boss.position.0, boss.position.1, 0.2),
+            GoldPile {
+                count: boss.count,
+                gold_cap: boss.cap,
+            },
+            Boss {
+                phase: boss.phase,
+                defeat,
+            },
+        );
+    }
+
+    *pending = PendingLoad::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::Status;
+    use crate::test_app::TestApp;
+    use crate::waves::{SpawnAt, WaveScript};
+
+    fn sample() -> SaveGame {
+        SaveGame {
+            version: SAVE_VERSION,
+            hexes: vec![
+                SavedHex {
+                    coords: HexCoords::new(),
+                    terrain: Terrain::Grassland,
+                    gold: 2,
+                    max_gold: 3,
+                    regen_time: 7.5,
+                    regen_elapsed: 1.25,
+                    blocked: false,
+                },
+                SavedHex {
+                    coords: HexCoords::from((1, 0)),
+                    terrain: Terrain::Rock,
+                    gold: 0,
+                    max_gold: 5,
+                    regen_time: 10.0,
+                    regen_elapsed: 0.0,
+                    blocked: true,
+                },
+            ],
+            growth: None,
+            piles: vec![SavedPile {
+                coords: HexCoords::new(),
+                count: 40,
+                cap: 500,
+            }],
+            previews: vec![SavedPreview {
+                coords: HexCoords::from((0, 1)),
+                kind: ShootType::Arc,
+                count: 3,
+                cap: 7,
+            }],
+            towers: vec![SavedTower {
+                coords: HexCoords::from((-1, 0)),
+                kind: ShootType::Bullet,
+                refund: 4,
+                cooldown_elapsed: 0.5,
+                can_shoot: false,
+                mining_elapsed: 2.0,
+            }],
+            gold: vec![(10.5, -3.0)],
+            enemies: vec![SavedEnemy {
+                position: (200.0, 150.0),
+                has_gold: true,
+                health: Some(4),
+                kind: "thief".to_string(),
+                carrying: Some(3),
+                status: vec![StatusHit {
+                    status: Status::Slow(0.5),
+                    seconds: 1.25,
+                }],
+            }],
+            boss: Some(SavedBoss {
+                position: (400.0, 20.0),
+                count: 120,
+                cap: 750,
+                phase: 1,
+                defeat_elapsed: None,
+            }),
+            waves: SavedWaves {
+                started: 3,
+                countdown: 12.0,
+                countdown_elapsed: 4.5,
+                queue: vec![QueuedSpawn {
+                    kind: "runner".to_string(),
+                    from: SpawnAt::Arc(90.0, 180.0),
+                    wait: 0.6,
+                }],
+                current: Some(3),
+            },
+            tower_cost: 11,
+            tower_count: 3,
+            boss_spawned: false,
+            lives: 8,
+        }
+    }
+
+    #[test]
+    fn round_trip_through_ron() {
+        let save = sample();
+        let text = save.to_ron().unwrap();
+        assert_eq!(SaveGame::from_ron(&text).unwrap(), save);
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let mut save = sample();
+        save.version = SAVE_VERSION + 1;
+        let text = save.to_ron().unwrap();
+        assert!(matches!(
+            SaveGame::from_ron(&text),
+            Err(LoadError::Version(v)) if v == SAVE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn collects_a_running_game() {
+        let mut world = World::new();
+        world.insert_resource(HexGrowth::none());
+        world.insert_resource(TowerSpawnCost { cost: 9 });
+        world.insert_resource(TowerCount {
+            count: 2,
+            boss_spawned: false,
+        });
+        let mut waves = Waves::new(&WaveScript::default());
+        waves.start_next(&WaveScript::default());
+        world.insert_resource(waves);
+        world.insert_resource(Lives::new());
+
+        let center = HexCoords::new();
+        let tower_coords = HexCoords::from((1, 0));
+        let preview_coords = HexCoords::from((0, 1));
+        world
+            .spawn()
+            .insert(Hex::new(center, Terrain::Grassland, 1, 3, 7.5))
+            .insert(GoldPile {
+                count: 25,
+                gold_cap: 500,
+            });
+        let mut tower = Tower::new(tower_coords, 4);
+        tower.shoot_type = ShootType::Arc;
+        world
+            .spawn()
+            .insert(Hex::new(tower_coords, Terrain::Grassland, 1, 3, 7.5))
+            .insert(tower)
+            .insert(GoldSpawner::new())
+            .insert(BombTower);
+        world
+            .spawn()
+            .insert(Hex::new(preview_coords, Terrain::Wasteland, 1, 3, 7.5))
+            .insert(TowerPreview {})
+            .insert(GoldPile {
+                count: 2,
+                gold_cap: 9,
+            });
+        world
+            .spawn()
+            .insert(Hex::new(HexCoords::from((-1, 0)), Terrain::Rock, 0, 3, 7.5))
+            .insert(Blocked);
+        world
+            .spawn()
+            .insert(Transform::from_xyz(5.0, 6.0, 0.3))
+            .insert(Gold);
+        world
+            .spawn()
+            .insert(Transform::from_xyz(300.0, 0.0, 0.3))
+            .insert(Enemy {
+                has_gold: true,
+                ..Enemy::new(EnemyTypes::default().get("tank").unwrap())
+            });
+
+        let save = SaveGame::collect(&mut world);
+        assert_eq!(save.hexes.len(), 4);
+        assert!(save.hexes.iter().any(|h| h.blocked));
+        assert_eq!(
+            save.piles,
+            vec![SavedPile {
+                coords: center,
+                count: 25,
+                cap: 500,
+            }]
+        );
+        assert_eq!(save.previews.len(), 1);
+        assert_eq!(save.previews[0].kind, ShootType::Bullet);
+        assert_eq!(save.towers.len(), 1);
+        assert_eq!(save.towers[0].kind, ShootType::Arc);
+        assert_eq!(save.towers[0].refund, 4);
+        assert_eq!(save.gold, vec![(5.0, 6.0)]);
+        assert!(save.enemies[0].has_gold);
+        assert_eq!(save.enemies[0].kind.as_deref(), Some("tank"));
+        assert_eq!(save.boss, None);
+        assert_eq!(save.tower_cost, 9);
+        assert_eq!(save.tower_count, 2);
+        assert_eq!(save.waves.started, 1);
+        assert_eq!(save.waves.current, Some(1));
+        assert!(!save.waves.queue.is_empty());
+
+        let text = save.to_ron().unwrap();
+        assert_eq!(SaveGame::from_ron(&text).unwrap(), save);
+    }
+
+    // a board that loads as is. Collected saves come out in this order
+    fn loadable() -> SaveGame {
+        let mut hexes: Vec<SavedHex> = HexCoords::new()
+            .spiral(1)
+            .into_iter()
+            .map(|coords| SavedHex {
+                coords,
+                terrain: Terrain::Grassland,
+                gold: 1,
+                max_gold: 3,
+                regen_time: 7.5,
+                regen_elapsed: 1.25,
+                blocked: false,
+            })
+            .collect();
+        hexes.sort_by_key(|h| <(isize, isize)>::from(h.coords));
+        let blocked = HexCoords::from((1, -1));
+        for h in hexes.iter_mut().filter(|h| h.coords == blocked) {
+            h.terrain = Terrain::Rock;
+            h.blocked = true;
+        }
+
+        let mut save = sample();
+        save.hexes = hexes;
+        save.growth = Some(SavedGrowth {
+            radius: 1,
+            max_radius: 2,
+            ring_time: 60.0,
+            ring: HexCoords::new().get_ring(2),
+            step_time: 3.0,
+            elapsed: 0.5,
+            defaults: HexDefaults::default(),
+        });
+        save.enemies.push(SavedEnemy {
+            position: (-250.0, 40.0),
+            has_gold: false,
+            health: None,
+            kind: "grunt".to_string(),
+            carrying: None,
+            status: Vec::new(),
+        });
+        save.boss_spawned = true;
+        save
+    }
+
+    #[test]
+    fn loads_back_what_was_saved() {
+        let mut test = TestApp::new(1);
+        test.app
+            .insert_resource(CurrentMap {
+                handle: Handle::default(),
+                spawned: true,
+            })
+            .add_plugin(SavePlugin);
+
+        let save = loadable();
+        test.app.world.resource_mut::<PendingLoad>().save = Some(save.clone());
+        let _ = test
+            .app
+            .world
+            .resource_mut::<State<GameState>>()
+            .replace(GameState::Menu);
+        // without ticks, nothing moves or runs down once it's loaded
+        test.idle(5);
+        assert!(
+            test.resource::<PendingLoad>().save.is_none(),
+            "never loaded"
+        );
+
+        let mut loaded = SaveGame::collect(&mut test.app.world);
+        // enemies come back in whatever order the query likes
+        loaded
+            .enemies
+            .sort_by(|a, b| b.position.0.total_cmp(&a.position.0));
+        assert_eq!(loaded, save);
+    }
+}
@@ -0,0 +1,552 @@
+use bevy::prelude::*;
+use bevy::time::create_time_channels;
+use bevy::utils::{Duration, Instant};
+use bevy_rapier2d::prelude::*;
+use rand::prelude::*;
+use std::fs;
+use std::path::Path;
+
+use crate::enemies::{
+    Boss, BossCapEvent, BossSpawnEvent, Dead, Enemy, SpawnEnemyEvent, BOSS_HEALTH,
+};
+use crate::game_assets::GameAssets;
+use crate::game_time::{GameTick, GameTime, TickStep, TICK};
+use crate::gold::{Gold, GoldPile, PileRemoveEvent, PileSpawnEvent};
+use crate::hex::{
+    spawn_hex, Blocked, Hex, HexAssets, HexCollection, HexCoords, HexGrowth, HexSpawnEvent,
+};
+use crate::lives::{GameOverEvent, GameOverReason, GoldEscapedEvent, Lives};
+use crate::map::{HexMap, DEFAULT_MAP};
+use crate::replay::{Playback, Replay};
+use crate::rng::GameRng;
+use crate::tower::{
+    spawn_tower_preview, PlaceTowerPreviewEvent, ShootType, Tower, TowerBuiltEvent, TowerPreview,
+    TOWER_COST_GROWTH,
+};
+use crate::waves::{WaveScript, WaveStarted};
+use crate::{GamePlugins, GameState, MouseWorldPos};
+
+pub const USAGE: &str = "usage: simulate [--minutes N] [--step SECONDS] [--order spiral|random] [--seed N] [--map PATH] [--replay PATH]";
+
+// how often the pretend player scoops up the loose gold
+const HAND_TIME: f32 = 0.5;
+// every nth tower in the spiral order is a bomb tower
+const BOMB_EVERY: u32 = 3;
+
+/// How to run the game without a window
+pub struct SimSettings {
+    pub minutes: f32,
+    // seconds of game time per update
+    pub step: f32,
+    pub order: BuildOrder,
+    pub seed: u64,
+    pub map: String,
+    // played instead of the bot. Its seed replaces the one above
+    pub replay: Option<String>,
+}
+
+impl Default for SimSettings {
+    fn default() -> Self {
+        SimSettings {
+            minutes: 15.0,
+            step: 1.0 / 60.0,
+            order: BuildOrder::Spiral,
+            seed: 0,
+            map: format!("assets/{DEFAULT_MAP}"),
+            replay: None,
+        }
+    }
+}
+
+impl SimSettings {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut settings = SimSettings::default();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| format!("{flag} needs a value"))?;
+            let bad = || format!("bad value for {flag}: {value}");
+            match flag.as_str() {
+                "--minutes" => settings.minutes = value.parse().map_err(|_| bad())?,
+                "--step" => settings.step = value.parse().map_err(|_| bad())?,
+                "--seed" => settings.seed = value.parse().map_err(|_| bad())?,
+                "--map" => settings.map = value.clone(),
+                "--replay" => settings.replay = Some(value.clone()),
+                "--order" => {
+                    settings.order = match value.as_str() {
+                        "spiral" => BuildOrder::Spiral,
+                        "random" => BuildOrder::Random,
+                        _ => return Err(format!("unknown build order: {value}")),
+                    }
+                }
+                _ => return Err(format!("unknown option: {flag}")),
+            }
+        }
+        if settings.step < TICK.as_secs_f32() {
+            return Err(format!(
+                "--step can't be shorter than a tick ({}s)",
+                TICK.as_secs_f32()
+            ));
+        }
+        Ok(settings)
+    }
+}
+
+/// Where the pretend player puts towers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildOrder {
+    // the free hex closest to the center. Every third one is a bomb tower
+    Spiral,
+    // any free hex, either kind
+    Random,
+}
+
+/// What happened over the run
+#[derive(Default, Debug, PartialEq)]
+pub struct SimStats {
+    pub elapsed: f32,
+    pub towers_built: u32,
+    pub waves_started: u32,
+    pub enemies_spawned: u32,
+    pub enemies_killed: u32,
+    pub gold_escaped: u32,
+    pub lives_left: u32,
+    pub boss_spawned_at: Option<f32>,
+    pub boss_defeated_at: Option<f32>,
+    // gold fed to the boss, out of its cap
+    pub boss_fill: Option<(u32, u32)>,
+    pub lost: Option<(f32, GameOverReason)>,
+}
+
+impl SimStats {
+    fn finished(&self) -> bool {
+        self.boss_defeated_at.is_some() || self.lost.is_some()
+    }
+}
+
+// the board without the asset server
+struct SimMap {
+    map: HexMap,
+    spawned: bool,
+}
+
+// the pretend player
+struct Bot {
+    order: BuildOrder,
+    placed: u32,
+    hand: Timer,
+}
+
+/// Plays the game headless and prints what happened
+pub fn run(settings: SimSettings) {
+    println!(
+        "Simulating {} minutes. order: {:?}, seed: {}, step: {}s, map: {}",
+        settings.minutes, settings.order, settings.seed, settings.step, settings.map
+    );
+    println!(
+        "TOWER_COST_GROWTH: {TOWER_COST_GROWTH}, scripted waves: {}, BOSS_HEALTH: {BOSS_HEALTH}",
+        WaveScript::default().waves.len()
+    );
+
+    let stats = match simulate(&settings) {
+        Ok(stats) => stats,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    println!("game time:       {}", clock(stats.elapsed));
+    println!("towers built:    {}", stats.towers_built);
+    println!("waves started:   {}", stats.waves_started);
+    println!("enemies spawned: {}", stats.enemies_spawned);
+    println!("enemies killed:  {}", stats.enemies_killed);
+    println!("gold escaped:    {}", stats.gold_escaped);
+    println!("lives left:      {}", stats.lives_left);
+    let boss = match (
+        stats.boss_spawned_at,
+        stats.boss_defeated_at,
+        stats.boss_fill,
+    ) {
+        (_, Some(t), _) => format!("defeated at {}", clock(t)),
+        (Some(t), None, Some((count, cap))) => {
+            format!("spawned at {}, fed {count}/{cap}", clock(t))
+        }
+        _ => "never spawned".to_string(),
+    };
+    println!("boss:            {boss}");
+    let result = match stats.lost {
+        Some((t, reason)) => format!("lost at {}. {}", clock(t), reason.description()),
+        None if stats.boss_defeated_at.is_some() => "won".to_string(),
+        None => "survived".to_string(),
+    };
+    println!("result:          {result}");
+}
+
+/// Runs the game at a fixed step until the time is up, or it's won or lost
+pub fn simulate(settings: &SimSettings) -> Result<SimStats, String> {
+    let text = fs::read_to_string(&settings.map)
+        .map_err(|e| format!("couldn't read {}: {e}", settings.map))?;
+    let map =
+        HexMap::from_ron(&text).map_err(|e| format!("couldn't parse {}: {e}", settings.map))?;
+
+    let mut app = headless_app(settings, map);
+    if let Some(path) = &settings.replay {
+        let replay = Replay::read(Path::new(path)).map_err(|e| format!("{path}: {e}"))?;
+        app.insert_resource(Playback::new(replay));
+    }
+
+    // feed Time a fixed step instead of the wall clock
+    let (time_sender, time_receiver) = create_time_channels();
+    app.insert_resource(time_receiver);
+    let start = Instant::now();
+    let step = Duration::from_secs_f32(settings.step);
+    let steps = (settings.minutes * 60.0 / settings.step).ceil() as u32;
+
+    for i in 0..=steps {
+        let _ = time_sender.0.try_send(start + step * i);
+        app.update();
+        if app.world.resource::<SimStats>().finished() {
+            break;
+        }
+        // nobody to carry on once it's over
+        if settings.replay.is_some() && !app.world.resource::<Playback>().playing() {
+            break;
+        }
+    }
+
+    let lives = app.world.resource::<Lives>().count;
+    let boss_fill = app
+        .world
+        .query_filtered::<&GoldPile, With<Boss>>()
+        .iter(&app.world)
+        .next()
+        .map(|pile| (pile.count, pile.gold_cap));
+    let mut stats = app.world.remove_resource::<SimStats>().unwrap_or_default();
+    stats.lives_left = lives;
+    stats.boss_fill = boss_fill;
+    Ok(stats)
+}
+
+/// The game without a window, already playing
+///
+/// No board and nobody playing it. The simulation and tests bring those.
+pub fn headless_game(seed: u64) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(bevy::transform::TransformPlugin)
+        .add_plugin(bevy::hierarchy::HierarchyPlugin)
+        // empty handles and idle input stand in for the window
+        .init_resource::<GameAssets>()
+        .init_resource::<HexAssets>()
+        .init_resource::<Input<KeyCode>>()
+        .init_resource::<Input<MouseButton>>()
+        .init_resource::<Windows>()
+        .add_plugins(GamePlugins)
+        // the seed drives spawns and jitter alike
+        .insert_resource(GameRng::new(seed))
+        .insert_resource(MouseWorldPos(Vec2::ONE * 10000.0))
+        .insert_resource(RapierConfiguration {
+            gravity: Vec2::ZERO,
+            ..default()
+        })
+        .add_state(GameState::Playing);
+    app
+}
+
+fn headless_app(settings: &SimSettings, map: HexMap) -> App {
+    // the seed drives the bot too
+    let mut app = headless_game(settings.seed);
+    app.insert_resource(SimMap {
+        map,
+        spawned: false,
+    })
+    .insert_resource(Bot {
+        order: settings.order,
+        placed: 0,
+        hand: Timer::from_seconds(HAND_TIME, true),
+    })
+    .insert_resource(SimStats::default())
+    // same as spawn_map, or the treasury is sometimes lost
+    .add_system(spawn_board.before(spawn_hex))
+    .add_system_set(
+        SystemSet::on_update(GameState::Playing)
+            .with_system(place_towers.before(spawn_tower_preview)),
+    )
+    // steps are never shorter than a tick, so every frame ticks
+    // and no events or kills are missed
+    .add_system_set_to_stage(GameTick, TickStep::Timers.set().with_system(deliver_gold))
+    .add_system_set_to_stage(GameTick, TickStep::Count.set().with_system(count_stats));
+    app
+}
+
+fn spawn_board(
+    mut sim_map: ResMut<SimMap>,
+    mut growth: ResMut<HexGrowth>,
+    mut ev_spawn: EventWriter<HexSpawnEvent>,
+    mut ev_pile: EventWriter<PileSpawnEvent>,
+) {
+    if sim_map.spawned {
+        return;
+    }
+    sim_map.spawned = true;
+    ev_spawn.send_batch(sim_map.map.spawn_events().into_iter());
+    ev_pile.send_batch(sim_map.map.pile_events().into_iter());
+    *growth = sim_map.map.growth();
+}
+
+// one preview at a time, paid off before the next goes down
+fn place_towers(
+    mut bot: ResMut<Bot>,
+    mut rng: ResMut<GameRng>,
+    playback: Res<Playback>,
+    q_previews: Query<(), With<TowerPreview>>,
+    q_boss: Query<(), With<Boss>>,
+    q_empty_hexes: Query<
+        &Hex,
+        (
+            Without<TowerPreview>,
+            Without<Tower>,
+            Without<GoldPile>,
+            Without<Blocked>,
+        ),
+    >,
+    mut ev_place: EventWriter<PlaceTowerPreviewEvent>,
+) {
+    // once the boss is out every gold goes to beating it
+    if playback.playing() || !q_previews.is_empty() || !q_boss.is_empty() {
+        return;
+    }
+
+    let mut free: Vec<HexCoords> = q_empty_hexes
+        .iter()
+        // the center is kept for the treasury
+        .filter(|hex| hex.terrain.buildable() && hex.coords != HexCoords::new())
+        .map(|hex| hex.coords)
+        .collect();
+    if free.is_empty() {
+        return;
+    }
+    // queries don't promise an order. Seeded runs should match
+    free.sort_by_key(|&c| (c.distance(HexCoords::new()), <(isize, isize)>::from(c)));
+
+    let (coords, shoot_type) = match bot.order {
+        BuildOrder::Spiral => {
+            let bomb = bot.placed % BOMB_EVERY == BOMB_EVERY - 1;
+            (
+                free[0],
+                if bomb {
+                    ShootType::Arc
+                } else {
+                    ShootType::Bullet
+                },
+            )
+        }
+        BuildOrder::Random => {
+            let coords = free[rng.bot.gen_range(0..free.len() as u32) as usize];
+            let bomb = rng.bot.gen_bool(1.0 / BOMB_EVERY as f64);
+            (
+                coords,
+                if bomb {
+                    ShootType::Arc
+                } else {
+                    ShootType::Bullet
+                },
+            )
+        }
+    };
+    bot.placed += 1;
+    ev_place.send(PlaceTowerPreviewEvent { coords, shoot_type });
+}
+
+// a tidy player. Every so often all the loose gold gets dropped on
+// the boss, then the tower being built, then the center pile.
+// When a tower needs paying for, the center pile gets broken open
+fn deliver_gold(
+    mut bot: ResMut<Bot>,
+    time: Res<GameTime>,
+    playback: Res<Playback>,
+    mut q_gold: Query<&mut Transform, With<Gold>>,
+    q_previews: Query<&Transform, (With<TowerPreview>, Without<Gold>)>,
+    q_boss: Query<&Transform, (With<Boss>, Without<Gold>)>,
+    q_piles: Query<(&Transform, &GoldPile), (With<Hex>, Without<TowerPreview>, Without<Gold>)>,
+    q_hexes: Query<(), (With<Hex>, Without<Tower>, Without<TowerPreview>)>,
+    hex_collect: Res<HexCollection>,
+    mut ev_pile_spawn: EventWriter<PileSpawnEvent>,
+    mut ev_pile_remove: EventWriter<PileRemoveEvent>,
+) {
+    if playback.playing() || !bot.hand.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let center = HexCoords::new();
+    let treasury = hex_collect
+        .hexes
+        .get(&center)
+        .and_then(|&e| q_piles.get(e).ok());
+
+    let preview = q_previews.iter().next();
+    if preview.is_some() && q_gold.is_empty() {
+        if let Some((_, pile)) = treasury {
+            if pile.count > 0 {
+                ev_pile_remove.send(PileRemoveEvent { coords: center });
+            }
+        }
+        return;
+    }
+
+    // beating the boss wins, so it comes first
+    let target = q_boss
+        .iter()
+        .next()
+        .or(preview)
+        .or_else(|| treasury.map(|(trans, _)| trans));
+    match target {
+        Some(target) => {
+            for mut trans in q_gold.iter_mut() {
+                trans.translation.x = target.translation.x;
+                trans.translation.y = target.translation.y;
+            }
+        }
+        None => {
+            // somewhere to keep the spare gold
+            let free = hex_collect
+                .hexes
+                .get(&center)
+                .is_some_and(|&e| q_hexes.get(e).is_ok());
+            if free && !q_gold.is_empty() {
+                ev_pile_spawn.send(PileSpawnEvent::new(center));
+            }
+        }
+    }
+}
+
+fn count_stats(
+    mut stats: ResMut<SimStats>,
+    time: Res<GameTime>,
+    q_killed: Query<(), (With<Enemy>, Added<Dead>)>,
+    mut ev_built: EventReader<TowerBuiltEvent>,
+    mut ev_wave: EventReader<WaveStarted>,
+    mut ev_enemy: EventReader<SpawnEnemyEvent>,
+    mut ev_escaped: EventReader<GoldEscapedEvent>,
+    mut ev_boss_spawn: EventReader<BossSpawnEvent>,
+    mut ev_boss_cap: EventReader<BossCapEvent>,
+    mut ev_game_over: EventReader<GameOverEvent>,
+) {
+    stats.elapsed = time.elapsed_seconds();
+    let now = stats.elapsed;
+
+    stats.towers_built += ev_built.iter().count() as u32;
+    stats.waves_started += ev_wave.iter().count() as u32;
+    stats.enemies_spawned += ev_enemy.iter().count() as u32;
+    stats.enemies_killed += q_killed.iter().count() as u32;
+    stats.gold_escaped += ev_escaped.iter().count() as u32;
+    if ev_boss_spawn.iter().next().is_some() {
+        stats.boss_spawned_at = Some(now);
+    }
+    if ev_boss_cap.iter().next().is_some() && stats.boss_defeated_at.is_none() {
+        stats.boss_defeated_at = Some(now);
+    }
+    if let Some(ev) = ev_game_over.iter().next() {
+        if stats.lost.is_none() {
+            stats.lost = Some((now, ev.reason));
+        }
+    }
+}
+
+fn clock(seconds: f32) -> String {
+    let seconds = seconds as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_time::Frame;
+    use crate::replay::{PlayerCommand, RecordedCommand};
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parses_args() {
+        let settings =
+            SimSettings::from_args(&args("--minutes 3 --order random --seed 7")).unwrap();
+        assert_eq!(settings.minutes, 3.0);
+        assert_eq!(settings.order, BuildOrder::Random);
+        assert_eq!(settings.seed, 7);
+
+        assert!(SimSettings::from_args(&args("--minutes")).is_err());
+        assert!(SimSettings::from_args(&args("--order sideways")).is_err());
+        assert!(SimSettings::from_args(&args("--speed 2")).is_err());
+        assert!(SimSettings::from_args(&args("--step 0.001")).is_err());
+    }
+
+    #[test]
+    fn builds_towers_headless() {
+        let settings = SimSettings {
+            minutes: 1.0,
+            step: 1.0 / 30.0,
+            map: format!("{}/assets/maps/default.map.ron", env!("CARGO_MANIFEST_DIR")),
+            ..default()
+        };
+        let stats = simulate(&settings).unwrap();
+        assert!(stats.elapsed > 55.0);
+        assert!(stats.towers_built > 0);
+        assert!(stats.enemies_spawned > 0);
+    }
+
+    #[test]
+    fn replays_play_out_the_same() {
+        // half a minute of uneven frames, building and dragging gold
+        let mut replay = Replay::new(5);
+        for i in 0..900 {
+            replay.push_frame(Frame {
+                ticks: [1, 2, 0][i % 3],
+                speed: 1.0,
+            });
+        }
+        let mut command = |frame, command| {
+            replay.push(RecordedCommand {
+                frame,
+                tick: frame as u64,
+                command,
+            })
+        };
+        command(
+            30,
+            PlayerCommand::PlaceTower {
+                coords: HexCoords::from((1, 0)),
+                shoot_type: ShootType::Bullet,
+            },
+        );
+        command(
+            60,
+            PlayerCommand::RemovePile {
+                coords: HexCoords::new(),
+            },
+        );
+        command(
+            90,
+            PlayerCommand::Mouse {
+                position: (40.0, 0.0),
+            },
+        );
+
+        let path = std::env::temp_dir().join("hex_defense_replay_test.ron");
+        replay.write(&path).unwrap();
+        let settings = SimSettings {
+            map: format!("{}/assets/maps/default.map.ron", env!("CARGO_MANIFEST_DIR")),
+            replay: Some(path.to_string_lossy().into_owned()),
+            ..default()
+        };
+        let first = simulate(&settings).unwrap();
+        let second = simulate(&settings).unwrap();
+        let _ = fs::remove_file(&path);
+
+        // every recorded tick ran, and no more
+        assert_eq!(first.elapsed, 900.0 * TICK.as_secs_f32());
+        assert!(first.enemies_spawned > 0);
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,225 @@
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use serde::{Deserialize, Serialize};
+use std::mem::discriminant;
+
+use crate::enemies::{DamageEvent, Dead};
+use crate::game_time::{GameTick, GameTime, TickStep};
+
+// burns stack up to this many at once
+const MAX_BURNS: usize = 3;
+// seconds between one burn and the next
+const BURN_TIME: f32 = 1.0;
+
+pub struct StatusPlugin;
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        // burns are hits, so they go in before anything else lands
+        app.add_system_set_to_stage(GameTick, TickStep::Timers.set().with_system(tick_status));
+    }
+}
+
+/// Something done to an enemy for a while
+// towers hand these out through ShootType::status
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    // moves at this fraction of its speed
+    Slow(f32),
+    // this much damage every BURN_TIME, before armor
+    Burn(u32),
+    // doesn't move at all
+    Stun,
+    // every hit on it does this many times the damage
+    Mark(f32),
+}
+
+/// A status and how long it lasts
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct StatusHit {
+    pub status: Status,
+    pub seconds: f32,
+}
+
+/// Everything an enemy is suffering from
+///
+/// Slows, stuns and marks don't stack. The strongest one counts,
+/// for as long as the longest one would have lasted.
+/// Burns stack up to MAX_BURNS, each on its own clock.
+#[derive(Component, Default)]
+pub struct StatusEffects {
+    effects: Vec<(Status, Timer)>,
+    // until the burns next hurt
+    burn: Timer,
+}
+
+impl StatusEffects {
+    pub fn apply(&mut self, hit: StatusHit) {
+        let timer = Timer::from_seconds(hit.seconds, false);
+        if let Status::Burn(_) = hit.status {
+            let burns = self.burns().count();
+            if burns == 0 {
+                self.burn = Timer::from_seconds(BURN_TIME, true);
+            }
+            if burns >= MAX_BURNS {
+                // the one closest to running out makes way
+                let oldest = self
+                    .effects
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (s, _))| matches!(s, Status::Burn(_)))
+                    .min_by_key(|(_, (_, t))| left(t))
+                    .map(|(i, _)| i);
+                if let Some(i) = oldest {
+                    self.effects.remove(i);
+                }
+            }
+            self.effects.push((hit.status, timer));
+            return;
+        }
+
+        let same = self
+            .effects
+            .iter_mut()
+            .find(|(s, _)| discriminant(s) == discriminant(&hit.status));
+        match same {
+            Some((status, current)) => {
+                if stronger(hit.status, *status) {
+                    *status = hit.status;
+                }
+                if left(&timer) > left(current) {
+                    *current = timer;
+                }
+            }
+            None => self.effects.push((hit.status, timer)),
+        }
+    }
+
+    /// What its speed is multiplied by
+    pub fn speed(&self) -> f32 {
+        self.effects.iter().fold(1.0, |speed, (s, _)| match s {
+            Status::Stun => 0.0,
+            Status::Slow(slow) => speed.min(*slow),
+            _ => speed,
+        })
+    }
+
+    /// What every hit on it is multiplied by
+    pub fn damage_taken(&self) -> f32 {
+        self.effects.iter().fold(1.0, |taken, (s, _)| match s {
+            Status::Mark(mark) => taken.max(*mark),
+            _ => taken,
+        })
+    }
+
+    /// What it's suffering from, and for how much longer
+    pub fn active(&self) -> impl Iterator<Item = StatusHit> + '_ {
+        self.effects.iter().map(|(status, timer)| StatusHit {
+            status: *status,
+            seconds: left(timer).as_secs_f32(),
+        })
+    }
+
+    fn burns(&self) -> impl Iterator<Item = u32> + '_ {
+        self.effects.iter().filter_map(|(s, _)| match s {
+            Status::Burn(damage) => Some(*damage),
+            _ => None,
+        })
+    }
+
+    /// Runs the clocks. Returns the burn damage due
+    fn tick(&mut self, delta: Duration) -> u32 {
+        let burning: u32 = self.burns().sum();
+        let due = if burning > 0 && self.burn.tick(delta).just_finished() {
+            burning
+        } else {
+            0
+        };
+        for (_, timer) in self.effects.iter_mut() {
+            timer.tick(delta);
+        }
+        self.effects.retain(|(_, timer)| !timer.finished());
+        due
+    }
+}
+
+fn left(timer: &Timer) -> Duration {
+    timer.duration() - timer.elapsed()
+}
+
+// only ever compared against the same kind
+fn stronger(a: Status, b: Status) -> bool {
+    match (a, b) {
+        (Status::Slow(a), Status::Slow(b)) => a < b,
+        (Status::Mark(a), Status::Mark(b)) => a > b,
+        _ => false,
+    }
+}
+
+fn tick_status(
+    mut q_status: Query<(Entity, &mut StatusEffects), Without<Dead>>,
+    time: Res<GameTime>,
+    mut ev_damage: EventWriter<DamageEvent>,
+) {
+    for (ent, mut effects) in q_status.iter_mut() {
+        let burn = effects.tick(time.delta());
+        if burn > 0 {
+            ev_damage.send(DamageEvent {
+                target: ent,
+                amount: burn,
+                status: None,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(status: Status, seconds: f32) -> StatusHit {
+        StatusHit { status, seconds }
+    }
+
+    #[test]
+    fn the_strongest_slow_counts_for_the_longest() {
+        let mut effects = StatusEffects::default();
+        effects.apply(hit(Status::Slow(0.5), 1.0));
+        effects.apply(hit(Status::Slow(0.8), 3.0));
+        assert_eq!(effects.speed(), 0.5);
+        assert_eq!(effects.active().count(), 1);
+
+        effects.tick(Duration::from_secs_f32(2.0));
+        assert_eq!(effects.speed(), 0.5);
+        effects.tick(Duration::from_secs_f32(1.5));
+        assert_eq!(effects.speed(), 1.0);
+    }
+
+    #[test]
+    fn stuns_stop_it_and_marks_hurt_more() {
+        let mut effects = StatusEffects::default();
+        effects.apply(hit(Status::Slow(0.5), 2.0));
+        effects.apply(hit(Status::Stun, 1.0));
+        effects.apply(hit(Status::Mark(1.5), 2.0));
+        assert_eq!(effects.speed(), 0.0);
+        assert_eq!(effects.damage_taken(), 1.5);
+
+        effects.tick(Duration::from_secs_f32(1.5));
+        assert_eq!(effects.speed(), 0.5);
+    }
+
+    #[test]
+    fn burns_stack_up_to_a_point() {
+        let mut effects = StatusEffects::default();
+        for _ in 0..MAX_BURNS + 2 {
+            effects.apply(hit(Status::Burn(2), 5.0));
+        }
+        assert_eq!(effects.burns().count(), MAX_BURNS);
+
+        let mut total = 0;
+        for _ in 0..4 {
+            total += effects.tick(Duration::from_secs_f32(BURN_TIME / 2.0));
+        }
+        assert_eq!(total, 2 * 2 * MAX_BURNS as u32);
+    }
+}
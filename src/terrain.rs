@@ -0,0 +1,101 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::palette::*;
+
+/// What a hex is made of
+/// Changes how much gold it gives, if you can build on it
+/// and how fast enemies can cross it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Terrain {
+    #[default]
+    Grassland,
+    // more gold per mine
+    RichVein,
+    // can't build or mine. Slows enemies
+    Rock,
+    // can't build or mine. Slows enemies a lot
+    Water,
+    // slow to regrow gold
+    Wasteland,
+}
+
+impl Terrain {
+    pub const ALL: [Terrain; 5] = [
+        Terrain::Grassland,
+        Terrain::RichVein,
+        Terrain::Rock,
+        Terrain::Water,
+        Terrain::Wasteland,
+    ];
+
+    /// Gold taken each time a tower mines this hex
+    pub fn mining_yield(self) -> u32 {
+        match self {
+            Terrain::Grassland | Terrain::Wasteland => 1,
+            Terrain::RichVein => 2,
+            Terrain::Rock | Terrain::Water => 0,
+        }
+    }
+
+    /// How fast gold grows back. 1.0 is normal
+    pub fn regen_rate(self) -> f32 {
+        match self {
+            Terrain::Grassland | Terrain::RichVein => 1.0,
+            Terrain::Wasteland => 0.5,
+            Terrain::Rock | Terrain::Water => 0.0,
+        }
+    }
+
+    pub fn buildable(self) -> bool {
+        !matches!(self, Terrain::Rock | Terrain::Water)
+    }
+
+    /// Multiplier on enemy speed while they're on this hex
+    pub fn enemy_speed(self) -> f32 {
+        match self {
+            Terrain::Grassland | Terrain::RichVein | Terrain::Wasteland => 1.0,
+            Terrain::Rock => 0.6,
+            Terrain::Water => 0.4,
+        }
+    }
+
+    pub fn sprite(self) -> &'static str {
+        match self {
+            Terrain::Grassland | Terrain::Wasteland => "sprites/Hex_15_13.png",
+            Terrain::RichVein | Terrain::Water => "sprites/HexGrass.png",
+            Terrain::Rock => "sprites/Hex.png",
+        }
+    }
+
+    // tint for the sprite on top of the hex
+    pub fn sprite_color(self) -> Color {
+        match self {
+            Terrain::Grassland | Terrain::Water => Color::WHITE,
+            Terrain::RichVein => GOLD,
+            Terrain::Rock => Color::GRAY,
+            Terrain::Wasteland => DARK_ORANGE,
+        }
+    }
+
+    // color of the hex itself
+    pub fn color(self) -> Color {
+        match self {
+            Terrain::Grassland => Color::rgb(0.388, 0.78, 0.3),
+            Terrain::RichVein => Color::rgb(0.45, 0.7, 0.25),
+            Terrain::Rock => Color::rgb(0.45, 0.45, 0.5),
+            Terrain::Water => Color::rgb(0.2, 0.45, 0.8),
+            Terrain::Wasteland => Color::rgb(0.6, 0.55, 0.35),
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Terrain::Grassland => "Grassland",
+            Terrain::RichVein => "Rich Vein. Mines 2 gold at a time",
+            Terrain::Rock => "Rock. Can't build here. Slows enemies",
+            Terrain::Water => "Water. Can't build here. Slows enemies a lot",
+            Terrain::Wasteland => "Wasteland. Gold grows back slowly",
+        }
+    }
+}
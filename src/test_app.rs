@@ -0,0 +1,153 @@
+use bevy::ecs::event::Event;
+use bevy::ecs::system::Resource;
+use bevy::prelude::*;
+use bevy::time::{create_time_channels, TimeSender};
+use bevy::utils::Instant;
+
+use crate::enemies::{Boss, BossSpawnEvent, Enemy, SpawnEnemyEvent};
+use crate::game_time::{Frame, GameTime, TICK};
+use crate::gold::SpawnGoldEvent;
+use crate::hex::{HexCollection, HexCoords, HexSpawnEvent};
+use crate::sim::headless_game;
+use crate::terrain::Terrain;
+use crate::tower::{PlaceTowerPreviewEvent, ShootType, Tower, TowerSpawnCost};
+
+/// The gameplay plugins on a small board, for tests to poke at
+///
+/// Every update is a frame of exactly one tick, and the clock is
+/// fed by hand, so tests count ticks instead of waiting on time.
+pub struct TestApp {
+    pub app: App,
+    clock: TimeSender,
+    start: Instant,
+    frames: u32,
+}
+
+impl TestApp {
+    /// A board of empty grassland, this many rings around the center
+    pub fn new(radius: u32) -> Self {
+        let mut app = headless_game(0);
+        let (clock, receiver) = create_time_channels();
+        app.insert_resource(receiver);
+        let mut test = TestApp {
+            app,
+            clock,
+            start: Instant::now(),
+            frames: 0,
+        };
+        for coords in HexCoords::new().spiral(radius) {
+            test.send(HexSpawnEvent {
+                coords,
+                terrain: Terrain::Grassland,
+                gold: 0,
+                max_gold: 0,
+                regen_time: 10.0,
+                blocked: false,
+            });
+        }
+        // the hexes slide into place before the game gets going
+        let intro = (2.5 / TICK.as_secs_f32()) as u32;
+        test.frames(intro, 0);
+        test
+    }
+
+    pub fn send<E: Event>(&mut self, ev: E) {
+        self.app.world.send_event(ev);
+    }
+
+    /// Runs this many frames of one tick each
+    pub fn ticks(&mut self, ticks: u32) {
+        self.frames(ticks, 1);
+    }
+
+    pub fn seconds(&mut self, seconds: f32) {
+        self.ticks((seconds / TICK.as_secs_f32()).ceil() as u32);
+    }
+
+    /// Runs frames that don't tick, so nothing in the game moves
+    pub fn idle(&mut self, frames: u32) {
+        self.frames(frames, 0);
+    }
+
+    fn frames(&mut self, frames: u32, ticks: u32) {
+        for _ in 0..frames {
+            // Time's first update only starts its clock
+            let _ = self.clock.0.try_send(self.start + TICK * self.frames);
+            self.frames += 1;
+            self.app
+                .world
+                .resource_mut::<GameTime>()
+                .play_frame(Frame { ticks, speed: 1.0 });
+            self.app.update();
+        }
+    }
+
+    pub fn hex(&self, coords: HexCoords) -> Entity {
+        self.app.world.resource::<HexCollection>().hexes[&coords]
+    }
+
+    pub fn get<C: Component>(&self, ent: Entity) -> Option<&C> {
+        self.app.world.get::<C>(ent)
+    }
+
+    pub fn count<C: Component>(&mut self) -> usize {
+        self.app
+            .world
+            .query_filtered::<(), With<C>>()
+            .iter(&self.app.world)
+            .count()
+    }
+
+    pub fn resource<R: Resource>(&self) -> &R {
+        self.app.world.resource::<R>()
+    }
+
+    /// Gold dropped straight onto a hex, like the player carried it there
+    pub fn drop_gold(&mut self, coords: HexCoords, amount: u32) {
+        let position = coords.to_position().extend(0.3);
+        for _ in 0..amount {
+            self.send(SpawnGoldEvent { position });
+        }
+    }
+
+    /// Places a tower and pays for it in full
+    pub fn build_tower(&mut self, coords: HexCoords, shoot_type: ShootType) {
+        let cost = self.resource::<TowerSpawnCost>().cost;
+        self.send(PlaceTowerPreviewEvent { coords, shoot_type });
+        self.ticks(1);
+        self.drop_gold(coords, cost);
+        // spawned, then landed, then built
+        self.ticks(3);
+        let hex = self.hex(coords);
+        assert!(self.get::<Tower>(hex).is_some(), "no tower at {coords:?}");
+    }
+
+    /// Spawns an enemy of this kind the way a wave would
+    pub fn spawn_enemy(&mut self, position: Vec2, kind: &str) -> Entity {
+        let before: Vec<Entity> = self.entities::<Enemy>();
+        self.send(SpawnEnemyEvent::new(position.extend(0.3), kind));
+        self.ticks(1);
+        self.entities::<Enemy>()
+            .into_iter()
+            .find(|e| !before.contains(e))
+            .expect("no enemy spawned")
+    }
+
+    /// Calls the boss in, like the last tower does
+    pub fn spawn_boss(&mut self) -> Entity {
+        self.send(BossSpawnEvent);
+        self.ticks(1);
+        self.app
+            .world
+            .query_filtered::<Entity, With<Boss>>()
+            .single(&self.app.world)
+    }
+
+    fn entities<C: Component>(&mut self) -> Vec<Entity> {
+        self.app
+            .world
+            .query_filtered::<Entity, With<C>>()
+            .iter(&self.app.world)
+            .collect()
+    }
+}
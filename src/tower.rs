@@ -4,18 +4,29 @@ use bevy::{
     utils::FloatOrd,
 };
 use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    enemies::{BossSpawnEvent, Dead, Enemy},
+    enemies::{BossSpawnEvent, DamageEvent, Dead, Enemy},
+    game_assets::GameAssets,
+    game_time::{GameTick, GameTime, Interpolated, TickStep},
     gold::*,
     hex::*,
-    palette::ORANGE,
-    tutorial::AcceptInput,
-    MouseWorldPos,
+    replay::{apply_commands, PlayerCommand, PlayerInput},
+    status::{Status, StatusHit},
+    GameState, MouseWorldPos,
 };
 
-const TOWER_COST_GROWTH: u32 = 2;
-const TOWERS_TO_SPAWN_BOSS: u32 = 10; //10
+pub const TOWER_COST_GROWTH: u32 = 2;
+pub const TOWERS_TO_SPAWN_BOSS: u32 = 10; //10
+pub const EXPLOSION_RADIUS: f32 = 30.0;
+// a basic enemy has 10 health
+pub const BULLET_DAMAGE: u32 = 10;
+pub const BOMB_DAMAGE: u32 = 10;
+// frost trades damage for holding enemies up
+const FROST_DAMAGE: u32 = 5;
+const FROST_SLOW: f32 = 0.5;
+const FROST_SECONDS: f32 = 2.0;
 pub struct TowerPlugin;
 
 impl Plugin for TowerPlugin {
@@ -27,37 +38,62 @@ impl Plugin for TowerPlugin {
             .add_event::<SpawnBombBulletEvent>()
             //.add_system(spawn_tower)
             //.add_system(tower_input)
-            .insert_resource(TowerSpawnCost { cost: 5 })
-            .insert_resource(TowerCount {
-                count: 0,
-                boss_spawned: false,
-            })
-            .add_system(tower_mouse_input)
-            .add_system(tower_key_input)
-            .add_system(spawn_tower_preview)
+            .insert_resource(TowerSpawnCost::new())
+            .insert_resource(TowerCount::new())
+            // towers are taken down before new ones go up,
+            // so the same clicks always build the same board
+            .add_system(
+                spawn_tower_preview
+                    .after(apply_commands)
+                    .after(remove_tower),
+            )
             .add_system(preview_paid_for)
-            .add_system(remove_tower)
-            .add_system(tower_shoot)
+            .add_system(remove_tower.after(apply_commands).after(preview_paid_for))
             .add_system(spawn_bullet)
-            .add_system(tick_bullet)
-            .add_system(move_bullet)
-            .add_system(bullet_hit)
-            .add_system(spawn_bomb_bullet)
-            .add_system(tick_bomb_bullet)
+            .add_system(spawn_bomb_bullet.after(apply_commands))
             .add_system(bomb_tower_build)
-            .add_system(tick_bomb_explosion)
-            .add_system(bomb_test);
+            .add_system(frost_tower_build)
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(reset_towers))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .label(PlayerInput)
+                    .with_system(tower_mouse_input)
+                    .with_system(tower_key_input)
+                    .with_system(bomb_test),
+            )
+            .add_system_set_to_stage(
+                GameTick,
+                TickStep::Timers
+                    .set()
+                    .with_system(tower_shoot)
+                    .with_system(tick_bullet),
+            )
+            .add_system_set_to_stage(
+                GameTick,
+                TickStep::Move
+                    .set()
+                    .with_system(move_bullet)
+                    .with_system(tick_bomb_bullet),
+            )
+            .add_system_set_to_stage(
+                GameTick,
+                TickStep::Hit
+                    .set()
+                    .with_system(bullet_hit)
+                    .with_system(tick_bomb_explosion),
+            );
         //.add_system(rotate_sprite);
     }
 }
 
 #[derive(Component)]
 pub struct Tower {
+    #[allow(dead_code)]
     pub coords: HexCoords,
     pub refund: u32,
-    shoot_type: ShootType,
-    shoot_timer: Timer,
-    can_shoot: bool,
+    pub shoot_type: ShootType,
+    pub shoot_timer: Timer,
+    pub can_shoot: bool,
     range: f32,
 }
 
@@ -74,14 +110,38 @@ impl Tower {
     }
 }
 
-#[derive(PartialEq)]
-enum ShootType {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShootType {
     Bullet,
     Arc,
+    // bullets that slow what they hit
+    Frost,
     //Pulse,
     //Laser,
 }
 
+impl ShootType {
+    /// How much one shot hurts, before armor
+    pub fn damage(self) -> u32 {
+        match self {
+            ShootType::Bullet => BULLET_DAMAGE,
+            ShootType::Arc => BOMB_DAMAGE,
+            ShootType::Frost => FROST_DAMAGE,
+        }
+    }
+
+    /// What one shot does to an enemy besides hurt it
+    pub fn status(self) -> Option<StatusHit> {
+        match self {
+            ShootType::Bullet | ShootType::Arc => None,
+            ShootType::Frost => Some(StatusHit {
+                status: Status::Slow(FROST_SLOW),
+                seconds: FROST_SECONDS,
+            }),
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct TowerPreview {}
 
@@ -92,30 +152,46 @@ pub struct PreviewTowerBundle {
 }
 
 #[derive(Component)]
-struct TowerSprite;
+pub struct TowerSprite;
 
-struct PlaceTowerPreviewEvent {
+pub struct PlaceTowerPreviewEvent {
     //position: Vec3,
-    coords: HexCoords,
-    shoot_type: ShootType,
+    pub coords: HexCoords,
+    pub shoot_type: ShootType,
 }
 
 // successfully build
 pub struct TowerBuiltEvent {
+    #[allow(dead_code)]
     pub coords: HexCoords,
 }
 
-struct TowerRemoveEvent {
-    coords: HexCoords,
+pub struct TowerRemoveEvent {
+    pub coords: HexCoords,
 }
 
-struct TowerSpawnCost {
-    cost: u32,
+pub struct TowerSpawnCost {
+    pub cost: u32,
 }
 
-struct TowerCount {
-    count: u32,
-    boss_spawned: bool,
+impl TowerSpawnCost {
+    pub fn new() -> Self {
+        TowerSpawnCost { cost: 5 }
+    }
+}
+
+pub struct TowerCount {
+    pub count: u32,
+    pub boss_spawned: bool,
+}
+
+impl TowerCount {
+    pub fn new() -> Self {
+        TowerCount {
+            count: 0,
+            boss_spawned: false,
+        }
+    }
 }
 
 // fn rotate_sprite(
@@ -136,27 +212,42 @@ struct TowerCount {
 //     }
 // }
 
+// towers only go on the hex under the mouse
+// not everything that's selected
 fn tower_mouse_input(
-    mut ev_place_preview: EventWriter<PlaceTowerPreviewEvent>,
-    q_selection: Query<&Hex, With<Selection>>,
+    mut ev_command: EventWriter<PlayerCommand>,
+    selection: Res<HexSelection>,
     input: Res<Input<MouseButton>>,
-    accept: Res<AcceptInput>,
+    keys: Res<Input<KeyCode>>,
+    q_buttons: Query<&Interaction, With<Button>>,
 ) {
-    if accept.0 && input.just_pressed(MouseButton::Left) {
-        for hex in q_selection.iter() {
-            ev_place_preview.send(PlaceTowerPreviewEvent {
+    // shift click is for selecting
+    if keys.any_pressed([KeyCode::LShift, KeyCode::RShift]) {
+        return;
+    }
+    // clicking a button shouldn't place a tower under it
+    if q_buttons.iter().any(|i| *i != Interaction::None) {
+        return;
+    }
+    if let Some(coords) = selection.hovered {
+        if input.just_pressed(MouseButton::Left) {
+            // ctrl click for a frost tower
+            let frost = keys.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+            ev_command.send(PlayerCommand::PlaceTower {
                 //position: trans.translation,
-                coords: hex.coords,
-                shoot_type: ShootType::Bullet,
+                coords,
+                shoot_type: if frost {
+                    ShootType::Frost
+                } else {
+                    ShootType::Bullet
+                },
             });
         }
-    }
-    // spawn a bomb tower
-    if accept.0 && input.just_pressed(MouseButton::Right) {
-        for hex in q_selection.iter() {
-            ev_place_preview.send(PlaceTowerPreviewEvent {
+        // spawn a bomb tower
+        if input.just_pressed(MouseButton::Right) {
+            ev_command.send(PlayerCommand::PlaceTower {
                 //position: trans.translation,
-                coords: hex.coords,
+                coords,
                 shoot_type: ShootType::Arc,
             });
         }
@@ -164,30 +255,32 @@ fn tower_mouse_input(
 }
 
 fn tower_key_input(
-    mut ev_remove_tower: EventWriter<TowerRemoveEvent>,
+    mut ev_command: EventWriter<PlayerCommand>,
     q_selection: Query<&Hex, With<Selection>>,
     input: Res<Input<KeyCode>>,
 ) {
     if input.just_pressed(KeyCode::X) {
         for hex in q_selection.iter() {
-            ev_remove_tower.send(TowerRemoveEvent { coords: hex.coords });
+            ev_command.send(PlayerCommand::RemoveTower { coords: hex.coords });
         }
     }
 }
 
 // where a tower will be
 // Still needs gold brought to it to build it
-fn spawn_tower_preview(
+pub fn spawn_tower_preview(
     mut commands: Commands,
     mut ev_place_preview: EventReader<PlaceTowerPreviewEvent>,
     q_empty_hexes: Query<
-        Entity,
+        (Entity, &Hex),
         (
-            With<Hex>,
-            (Without<TowerPreview>, Without<Tower>, Without<GoldPile>),
+            Without<TowerPreview>,
+            Without<Tower>,
+            Without<GoldPile>,
+            Without<Blocked>,
         ),
     >,
-    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
     mut cost: ResMut<TowerSpawnCost>,
     hex_collect: Res<HexCollection>,
 ) {
@@ -201,42 +294,18 @@ fn spawn_tower_preview(
         // if ==
         // it's probably faster
         if let Some(&e) = hex_collect.hexes.get(&ev.coords) {
-            if let Ok(ent) = q_empty_hexes.get(e) {
-                // empty hex exists
-                commands
-                    .entity(ent)
-                    .insert_bundle(PreviewTowerBundle {
-                        preview: TowerPreview {},
-                        pile: GoldPile::new(cost.cost),
-                    })
-                    .with_children(|parent| {
-                        parent
-                            .spawn_bundle(SpriteBundle {
-                                texture: asset_server.load("sprites/UnbuiltTower.png"),
-                                // sprite: Sprite {
-                                //     color: LIGHT_BLUE,
-                                //     custom_size: Some(Vec2::new(20.0, 20.0)),
-                                //     ..default()
-                                // },
-                                transform: Transform {
-                                    // spawn on top of the underlying hex
-                                    translation: Vec3 {
-                                        x: 0.0,
-                                        y: 0.0,
-                                        z: 0.2,
-                                    },
-                                    // undo the hex's rotation
-                                    rotation: Quat::from_rotation_z(-30.0 * DEG_TO_RAD),
-                                    ..default()
-                                },
-                                ..default()
-                            })
-                            .insert(TowerSprite);
-                    });
-
-                if ev.shoot_type == ShootType::Arc {
-                    commands.entity(ent).insert(BombTower);
+            if let Ok((ent, hex)) = q_empty_hexes.get(e) {
+                if !hex.terrain.buildable() {
+                    continue;
                 }
+                // empty hex exists
+                insert_preview(
+                    &mut commands,
+                    ent,
+                    GoldPile::new(cost.cost),
+                    ev.shoot_type,
+                    &game_assets,
+                );
                 // it is now a Hex, TowerPreview, GoldPile,
                 // with a sprite child
                 cost.cost += TOWER_COST_GROWTH;
@@ -245,15 +314,94 @@ fn spawn_tower_preview(
     }
 }
 
-fn preview_paid_for(
+/// Makes a hex into a tower preview waiting to be paid for
+/// Also used when loading a save
+pub fn insert_preview(
+    commands: &mut Commands,
+    ent: Entity,
+    pile: GoldPile,
+    shoot_type: ShootType,
+    game_assets: &GameAssets,
+) {
+    commands
+        .entity(ent)
+        .insert_bundle(PreviewTowerBundle {
+            preview: TowerPreview {},
+            pile,
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(tower_sprite(game_assets.unbuilt_tower.clone()))
+                .insert(TowerSprite);
+        });
+
+    match shoot_type {
+        ShootType::Arc => {
+            commands.entity(ent).insert(BombTower);
+        }
+        ShootType::Frost => {
+            commands.entity(ent).insert(FrostTower);
+        }
+        ShootType::Bullet => {}
+    }
+}
+
+/// Puts a finished tower straight onto a hex when loading a save
+pub fn insert_tower(
+    commands: &mut Commands,
+    ent: Entity,
+    tower: Tower,
+    spawner: GoldSpawner,
+    game_assets: &GameAssets,
+) {
+    match tower.shoot_type {
+        ShootType::Arc => {
+            commands.entity(ent).insert(BombTower);
+        }
+        ShootType::Frost => {
+            commands.entity(ent).insert(FrostTower);
+        }
+        ShootType::Bullet => {}
+    }
+    commands
+        .entity(ent)
+        .insert(tower)
+        .insert(spawner)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(tower_sprite(game_assets.tower.clone()))
+                .insert(TowerSprite);
+        });
+}
+
+fn tower_sprite(texture: Handle<Image>) -> SpriteBundle {
+    SpriteBundle {
+        texture,
+        transform: Transform {
+            // spawn on top of the underlying hex
+            translation: Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.2,
+            },
+            // undo the hex's rotation
+            rotation: Quat::from_rotation_z(-30.0 * DEG_TO_RAD),
+            ..default()
+        },
+        ..default()
+    }
+}
+
+pub fn preview_paid_for(
     mut commands: Commands,
     mut ev_pile_cap: EventReader<PileCapEvent>,
     q_preview_towers: Query<(Entity, &Children), (With<Hex>, With<GoldPile>, With<TowerPreview>)>,
     mut q_child: Query<&mut Handle<Image>, With<TowerSprite>>,
-    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
     mut tower_count: ResMut<TowerCount>,
     mut ev_boss: EventWriter<BossSpawnEvent>,
     mut ev_remove_pile: EventWriter<PileRemoveEvent>,
+    mut ev_built: EventWriter<TowerBuiltEvent>,
     hex_collect: Res<HexCollection>,
 ) {
     for ev in ev_pile_cap.iter() {
@@ -269,7 +417,7 @@ fn preview_paid_for(
 
                     // change the sprite of the preview tower sprite to the built tower
                     if let Ok(mut s) = sprite {
-                        *s = asset_server.load("sprites/Tower.png");
+                        *s = game_assets.tower.clone();
                     }
                 }
 
@@ -290,6 +438,7 @@ fn preview_paid_for(
                 }
 
                 ev_remove_pile.send(PileRemoveEvent { coords: ev.coords });
+                ev_built.send(TowerBuiltEvent { coords: ev.coords });
 
                 break;
             }
@@ -307,7 +456,14 @@ fn bomb_tower_build(
     }
 }
 
-fn remove_tower(
+// same for frost
+fn frost_tower_build(mut q_frost: Query<&mut Tower, (Added<Tower>, With<FrostTower>)>) {
+    for mut t in q_frost.iter_mut() {
+        t.shoot_type = ShootType::Frost;
+    }
+}
+
+pub fn remove_tower(
     mut commands: Commands,
     mut ev_remove: EventReader<TowerRemoveEvent>,
     mut ev_spawn_gold: EventWriter<SpawnGoldEvent>,
@@ -320,6 +476,7 @@ fn remove_tower(
         Option<&GoldPile>,
         Option<&Tower>,
         Option<&BombTower>,
+        Option<&FrostTower>,
     )>,
     q_sprite: Query<Entity, With<TowerSprite>>,
     mut counter: ResMut<TowerCount>,
@@ -329,8 +486,17 @@ fn remove_tower(
 ) {
     for ev in ev_remove.iter() {
         if let Some(&e) = hex_collect.hexes.get(&ev.coords) {
-            if let Ok((ent, children, trans, _hex, opt_preview, opt_pile, opt_tower, opt_bomb)) =
-                q_towers.get(e)
+            if let Ok((
+                ent,
+                children,
+                trans,
+                _hex,
+                opt_preview,
+                opt_pile,
+                opt_tower,
+                opt_bomb,
+                opt_frost,
+            )) = q_towers.get(e)
             {
                 // for (ent, children, trans, hex, opt_preview, opt_pile, opt_tower, opt_bomb) in
                 //     q_towers.iter()
@@ -360,6 +526,10 @@ fn remove_tower(
                     commands.entity(ent).remove::<BombTower>();
                 }
 
+                if opt_frost.is_some() {
+                    commands.entity(ent).remove::<FrostTower>();
+                }
+
                 if opt_preview.is_some() {
                     commands.entity(ent).remove::<TowerPreview>();
                 }
@@ -391,7 +561,7 @@ fn tower_shoot(
     q_enemies: Query<(&Transform, &Enemy)>,
     mut ev_shoot: EventWriter<SpawnBulletEvent>,
     mut ev_bomb: EventWriter<SpawnBombBulletEvent>,
-    time: Res<Time>,
+    time: Res<GameTime>,
 ) {
     for (t_trans, mut t) in q_towers.iter_mut() {
         if !t.can_shoot {
@@ -404,7 +574,7 @@ fn tower_shoot(
             // find a target
 
             match t.shoot_type {
-                ShootType::Bullet => {
+                ShootType::Bullet | ShootType::Frost => {
                     let direction = q_enemies
                         .iter()
                         .min_by_key(|target_transform| {
@@ -421,6 +591,8 @@ fn tower_shoot(
                             ev_shoot.send(SpawnBulletEvent {
                                 pos: t_trans.translation.truncate(),
                                 dir: direction.truncate(),
+                                damage: t.shoot_type.damage(),
+                                status: t.shoot_type.status(),
                             });
 
                             t.can_shoot = false;
@@ -452,6 +624,8 @@ fn tower_shoot(
                             ev_bomb.send(SpawnBombBulletEvent {
                                 start_pos: t_trans.translation.truncate(),
                                 target_dir: pos_prediction - t_trans.translation.truncate(),
+                                damage: t.shoot_type.damage(),
+                                status: t.shoot_type.status(),
                             });
                             t.can_shoot = false;
                         }
@@ -466,13 +640,17 @@ fn tower_shoot(
 pub struct Bullet {
     dir: Vec2,
     timer: Timer,
+    damage: u32,
+    status: Option<StatusHit>,
 }
 
 impl Bullet {
-    pub fn new(dir: Vec2) -> Self {
+    pub fn new(dir: Vec2, damage: u32, status: Option<StatusHit>) -> Self {
         Bullet {
             dir,
             timer: Timer::from_seconds(1.0, false),
+            damage,
+            status,
         }
     }
 }
@@ -480,18 +658,20 @@ impl Bullet {
 struct SpawnBulletEvent {
     pos: Vec2,
     dir: Vec2,
+    damage: u32,
+    status: Option<StatusHit>,
 }
 
 fn spawn_bullet(
     mut commands: Commands,
     mut ev_spawn_bullet: EventReader<SpawnBulletEvent>,
-    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
 ) {
     for ev in ev_spawn_bullet.iter() {
         //println!("Spawn a bullet. pos: {:?}, dir: {:?}", ev.pos, ev.dir);
         commands
             .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("sprites/Missile.png"),
+                texture: game_assets.missile.clone(),
                 sprite: Sprite {
                     // Flip the logo to the left
                     flip_x: { ev.dir.x > 0.0 },
@@ -514,14 +694,19 @@ fn spawn_bullet(
                 },
                 ..default()
             })
-            .insert(Bullet::new(ev.dir.normalize_or_zero()));
+            .insert(Bullet::new(
+                ev.dir.normalize_or_zero(),
+                ev.damage,
+                ev.status,
+            ))
+            .insert(Interpolated::new(ev.pos.extend(0.5)));
     }
 }
 
 fn tick_bullet(
     mut commands: Commands,
     mut q_bullet: Query<(Entity, &mut Bullet)>,
-    time: Res<Time>,
+    time: Res<GameTime>,
 ) {
     for (ent, mut b) in q_bullet.iter_mut() {
         if b.timer.tick(time.delta()).just_finished() {
@@ -530,7 +715,7 @@ fn tick_bullet(
     }
 }
 
-fn move_bullet(mut q_bullet: Query<(&mut Transform, &Bullet)>, time: Res<Time>) {
+fn move_bullet(mut q_bullet: Query<(&mut Transform, &Bullet)>, time: Res<GameTime>) {
     for (mut trans, b) in q_bullet.iter_mut() {
         trans.translation += b.dir.extend(0.0) * time.delta_seconds() * 400.0;
     }
@@ -538,22 +723,26 @@ fn move_bullet(mut q_bullet: Query<(&mut Transform, &Bullet)>, time: Res<Time>)
 
 pub fn bullet_hit(
     mut commands: Commands,
-    q_bullet: Query<(Entity, &Transform), With<Bullet>>,
-    q_enemies: Query<(Entity, &Transform), (Without<Bullet>, Without<Dead>, With<Enemy>)>,
+    q_bullet: Query<(Entity, &Transform, &Bullet)>,
+    q_enemies: Query<(Entity, &Transform, &Enemy), (Without<Bullet>, Without<Dead>)>,
+    mut ev_damage: EventWriter<DamageEvent>,
 ) {
-    for (b_ent, b_trans) in q_bullet.iter() {
-        for (e_ent, e_trans) in q_enemies.iter() {
+    for (b_ent, b_trans, bullet) in q_bullet.iter() {
+        for (e_ent, e_trans, enemy) in q_enemies.iter() {
             if collide(
                 b_trans.translation,
                 Vec2::new(6., 6.),
                 e_trans.translation,
-                Vec2::new(15., 15.),
+                Vec2::splat(enemy.size),
             )
             .is_some()
             {
                 //println!("Blam!");
-                // Todo drop gold
-                commands.entity(e_ent).insert(Dead);
+                ev_damage.send(DamageEvent {
+                    target: e_ent,
+                    amount: bullet.damage,
+                    status: bullet.status,
+                });
 
                 commands.entity(b_ent).despawn_recursive();
 
@@ -569,6 +758,21 @@ pub fn bullet_hit(
     }
 }
 
+// back to the menu. Get rid of everything from the last game
+// towers go with the hexes they're on
+fn reset_towers(
+    mut commands: Commands,
+    q_bullets: Query<Entity, Or<(With<Bullet>, With<BombBullet>, With<BombExplosion>)>>,
+    mut cost: ResMut<TowerSpawnCost>,
+    mut counter: ResMut<TowerCount>,
+) {
+    for ent in q_bullets.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+    *cost = TowerSpawnCost::new();
+    *counter = TowerCount::new();
+}
+
 #[derive(Component)]
 struct BombBullet {
     start_pos: Vec3,
@@ -576,27 +780,41 @@ struct BombBullet {
     end_dir: Vec2,
     //offset_dir: Vec2,
     timer: Timer,
+    damage: u32,
+    status: Option<StatusHit>,
 }
 
-struct SpawnBombBulletEvent {
-    start_pos: Vec2,
-    target_dir: Vec2,
+pub struct SpawnBombBulletEvent {
+    pub start_pos: Vec2,
+    pub target_dir: Vec2,
+    pub damage: u32,
+    pub status: Option<StatusHit>,
 }
 
 #[derive(Component)]
-struct BombTower;
+pub struct BombTower;
 
 #[derive(Component)]
-struct BombExplosion {
+pub struct FrostTower;
+
+#[derive(Component)]
+pub struct BombExplosion {
     danger_timer: Timer,
     lifetime_timer: Timer,
+    damage: u32,
+    status: Option<StatusHit>,
+    // each enemy is only hurt once per blast
+    hit: Vec<Entity>,
 }
 
 impl BombExplosion {
-    fn new() -> Self {
+    fn new(damage: u32, status: Option<StatusHit>) -> Self {
         BombExplosion {
             danger_timer: Timer::from_seconds(0.15, false),
             lifetime_timer: Timer::from_seconds(0.3, false),
+            damage,
+            status,
+            hit: Vec::new(),
         }
     }
 }
@@ -641,7 +859,7 @@ impl BombExplosion {
 fn spawn_bomb_bullet(
     mut commands: Commands,
     mut ev_spawn_bomb: EventReader<SpawnBombBulletEvent>,
-    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
 ) {
     for ev in ev_spawn_bomb.iter() {
         // make an arc
@@ -685,7 +903,7 @@ fn spawn_bomb_bullet(
 
         commands
             .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("sprites/Gold1.png"),
+                texture: game_assets.bomb.clone(),
                 sprite: Sprite {
                     // Flip the logo to the left
                     flip_x: { dir.x > 0.0 },
@@ -700,36 +918,39 @@ fn spawn_bomb_bullet(
                 },
                 ..default()
             })
+            .insert(Interpolated::new(start_pos))
             .insert(BombBullet {
                 start_pos,
                 start_dir,
                 end_dir,
                 //offset_dir: perp_up.normalize() * 100.0,
                 timer: Timer::from_seconds(1.0, false),
+                damage: ev.damage,
+                status: ev.status,
             });
     }
 }
 
 fn tick_bomb_bullet(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    game_assets: Res<GameAssets>,
     mut q_bombs: Query<(Entity, &mut Transform, &mut BombBullet)>,
-    time: Res<Time>,
+    time: Res<GameTime>,
 ) {
     for (ent, mut trans, mut bomb) in q_bombs.iter_mut() {
         if bomb.timer.tick(time.delta()).just_finished() {
             // blow up
             commands
                 .spawn_bundle(MaterialMesh2dBundle {
-                    mesh: meshes.add(shape::Circle::new(30.0).into()).into(),
-                    material: materials.add(ColorMaterial::from(ORANGE)),
+                    mesh: game_assets.explosion_mesh.clone(),
+                    material: game_assets.explosion_material.clone(),
                     transform: Transform::from_translation(trans.translation),
                     ..default()
                 })
-                .insert(Collider::ball(30.0))
+                // only drawn by the debug renderer. Hits are checked by hand
+                .insert(Collider::ball(EXPLOSION_RADIUS))
                 .insert(Sensor)
-                .insert(BombExplosion::new());
+                .insert(BombExplosion::new(bomb.damage, bomb.status));
             commands.entity(ent).despawn_recursive();
         }
 
@@ -763,17 +984,32 @@ fn tick_bomb_bullet(
     }
 }
 
-fn tick_bomb_explosion(
+pub fn tick_bomb_explosion(
     mut commands: Commands,
-    rapier_context: Res<RapierContext>,
-    mut q_bombs: Query<(Entity, &mut BombExplosion)>,
-    q_enemies: Query<Entity, With<Enemy>>,
-    time: Res<Time>,
+    mut q_bombs: Query<(Entity, &Transform, &mut BombExplosion)>,
+    // already dead ones are being despawned
+    q_enemies: Query<(Entity, &Transform, &Enemy), Without<Dead>>,
+    time: Res<GameTime>,
+    mut ev_damage: EventWriter<DamageEvent>,
 ) {
-    for (bomb_ent, mut bomb) in q_bombs.iter_mut() {
-        for enemy_ent in q_enemies.iter() {
-            if rapier_context.intersection_pair(bomb_ent, enemy_ent) == Some(true) {
-                commands.entity(enemy_ent).insert(Dead);
+    for (bomb_ent, bomb_trans, mut bomb) in q_bombs.iter_mut() {
+        if !bomb.danger_timer.finished() {
+            for (enemy_ent, e_trans, enemy) in q_enemies.iter() {
+                if bomb.hit.contains(&enemy_ent) {
+                    continue;
+                }
+                // the blast circle against the enemy's square
+                let d = (bomb_trans.translation - e_trans.translation).truncate();
+                let half = Vec2::splat(enemy.size / 2.0);
+                let nearest = d.clamp(-half, half);
+                if d.distance(nearest) < EXPLOSION_RADIUS {
+                    ev_damage.send(DamageEvent {
+                        target: enemy_ent,
+                        amount: bomb.damage,
+                        status: bomb.status,
+                    });
+                    bomb.hit.push(enemy_ent);
+                }
             }
         }
         // remove the art after 3s
@@ -790,12 +1026,135 @@ fn tick_bomb_explosion(
 fn bomb_test(
     input: Res<Input<KeyCode>>,
     mouse: Res<MouseWorldPos>,
-    mut ev_bomb: EventWriter<SpawnBombBulletEvent>,
+    mut ev_command: EventWriter<PlayerCommand>,
 ) {
     if input.just_pressed(KeyCode::Space) {
-        ev_bomb.send(SpawnBombBulletEvent {
-            start_pos: Vec2::ZERO,
-            target_dir: mouse.0,
+        ev_command.send(PlayerCommand::Bomb {
+            target: mouse.0.into(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemies::Boss;
+    use crate::status::StatusEffects;
+    use crate::test_app::TestApp;
+
+    #[test]
+    fn gold_builds_a_preview() {
+        let mut test = TestApp::new(1);
+        let coords = HexCoords::from((1, 0));
+        test.send(PlaceTowerPreviewEvent {
+            coords,
+            shoot_type: ShootType::Bullet,
+        });
+        test.ticks(1);
+        let hex = test.hex(coords);
+        assert!(test.get::<TowerPreview>(hex).is_some());
+        assert_eq!(test.get::<GoldPile>(hex).unwrap().gold_cap, 5);
+        // the next one costs more
+        assert_eq!(
+            test.resource::<TowerSpawnCost>().cost,
+            5 + TOWER_COST_GROWTH
+        );
+
+        // one short isn't enough
+        test.drop_gold(coords, 4);
+        test.ticks(3);
+        assert!(test.get::<Tower>(hex).is_none());
+        assert_eq!(test.get::<GoldPile>(hex).unwrap().count, 4);
+
+        test.drop_gold(coords, 1);
+        test.ticks(3);
+        assert!(test.get::<TowerPreview>(hex).is_none());
+        assert!(test.get::<Tower>(hex).is_some());
+        assert_eq!(test.resource::<TowerCount>().count, 1);
+        // every gold went into it
+        assert_eq!(test.count::<Gold>(), 0);
+    }
+
+    #[test]
+    fn bomb_towers_build_as_bombs() {
+        let mut test = TestApp::new(1);
+        let coords = HexCoords::from((0, 1));
+        test.build_tower(coords, ShootType::Arc);
+        let hex = test.hex(coords);
+        assert_eq!(test.get::<Tower>(hex).unwrap().shoot_type, ShootType::Arc);
+        assert_eq!(test.get::<GoldSpawner>(hex).unwrap().radius, 2);
+    }
+
+    #[test]
+    fn frost_towers_slow_what_they_hit() {
+        let mut test = TestApp::new(1);
+        let coords = HexCoords::from((1, 0));
+        test.build_tower(coords, ShootType::Frost);
+        let hex = test.hex(coords);
+        assert_eq!(test.get::<Tower>(hex).unwrap().shoot_type, ShootType::Frost);
+
+        let ent = test.spawn_enemy(coords.to_position() + Vec2::new(60.0, 0.0), "tank");
+        test.seconds(1.0);
+        let effects = test.get::<StatusEffects>(ent).unwrap();
+        assert_eq!(effects.speed(), FROST_SLOW);
+    }
+
+    #[test]
+    fn removing_a_tower_refunds_most_of_it() {
+        let mut test = TestApp::new(1);
+        let coords = HexCoords::from((1, 0));
+        test.build_tower(coords, ShootType::Bullet);
+        let hex = test.hex(coords);
+        assert_eq!(test.get::<Tower>(hex).unwrap().refund, 4);
+
+        test.send(TowerRemoveEvent { coords });
+        test.ticks(1);
+        assert!(test.get::<Tower>(hex).is_none());
+        assert!(test.get::<GoldSpawner>(hex).is_none());
+        assert_eq!(test.count::<Gold>(), 4);
+        // and the next tower is cheaper again
+        assert_eq!(test.resource::<TowerSpawnCost>().cost, 5);
+    }
+
+    #[test]
+    fn removing_a_preview_gives_back_what_was_paid() {
+        let mut test = TestApp::new(1);
+        let coords = HexCoords::from((-1, 0));
+        test.send(PlaceTowerPreviewEvent {
+            coords,
+            shoot_type: ShootType::Bullet,
+        });
+        test.ticks(1);
+        test.drop_gold(coords, 3);
+        test.ticks(3);
+
+        // X takes away both the preview and its pile
+        test.send(PileRemoveEvent { coords });
+        test.send(TowerRemoveEvent { coords });
+        test.ticks(1);
+        let hex = test.hex(coords);
+        assert!(test.get::<TowerPreview>(hex).is_none());
+        assert!(test.get::<GoldPile>(hex).is_none());
+        assert_eq!(test.count::<Gold>(), 3);
+    }
+
+    #[test]
+    fn enough_towers_bring_the_boss() {
+        let mut test = TestApp::new(2);
+        let ring: Vec<HexCoords> = HexCoords::new().spiral(2).into_iter().skip(1).collect();
+        for &coords in ring.iter().take(TOWERS_TO_SPAWN_BOSS as usize - 1) {
+            test.build_tower(coords, ShootType::Bullet);
+        }
+        assert_eq!(test.count::<Boss>(), 0);
+
+        test.build_tower(ring[TOWERS_TO_SPAWN_BOSS as usize - 1], ShootType::Bullet);
+        test.ticks(1);
+        assert_eq!(test.count::<Boss>(), 1);
+        assert!(test.resource::<TowerCount>().boss_spawned);
+
+        // only ever the one
+        test.build_tower(ring[TOWERS_TO_SPAWN_BOSS as usize], ShootType::Bullet);
+        test.ticks(1);
+        assert_eq!(test.count::<Boss>(), 1);
+    }
+}
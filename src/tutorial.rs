@@ -1,21 +1,24 @@
 use bevy::prelude::*;
 
-use crate::{enemies::BossCapEvent, hex::DEG_TO_RAD, StartSpawningEnemiesEvent};
+use crate::{enemies::BossCapEvent, hex::DEG_TO_RAD, lives::Lives, GameState};
 
 pub struct TutorialPlugin;
 
 impl Plugin for TutorialPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(start_menu)
-            .add_startup_system(tutorial_side_bar)
+        app.add_startup_system(tutorial_side_bar)
             .add_startup_system(transition_setup)
-            .add_event::<RemoveMenuEvent>()
             .add_event::<TransitionEvent>()
-            .insert_resource(AcceptInput(false))
             .add_system(button_system)
-            .add_system(remove_start_menu)
-            .add_system(allow_input)
-            .add_system(win_menu)
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(start_menu))
+            .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(remove_start_menu))
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(win_game))
+            .add_system_set(SystemSet::on_enter(GameState::Won).with_system(win_menu))
+            .add_system_set(SystemSet::on_exit(GameState::Won).with_system(remove_end_menu))
+            .add_system_set(SystemSet::on_enter(GameState::Lost).with_system(lose_menu))
+            .add_system_set(SystemSet::on_exit(GameState::Lost).with_system(remove_end_menu))
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(pause_menu))
+            .add_system_set(SystemSet::on_exit(GameState::Paused).with_system(remove_pause_menu))
             .add_system(toggle_tutorial)
             .add_system(toggle_transition)
             .add_system(start_transition)
@@ -26,8 +29,13 @@ impl Plugin for TutorialPlugin {
 #[derive(Component)]
 struct StartMenu;
 
+// what a button does when it's clicked
 #[derive(Component)]
-struct RemoveButton;
+enum MenuButton {
+    Start,
+    Continue,
+    Restart,
+}
 
 #[derive(Component)]
 struct ButtonInfo {
@@ -38,25 +46,20 @@ struct ButtonInfo {
 #[derive(Component)]
 struct EndMenu;
 
-struct RemoveMenuEvent;
-
-pub struct AcceptInput(pub bool);
-
 const NORMAL_BUTTOM: Color = Color::rgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTOM: Color = Color::rgb(0.25, 0.25, 0.25);
 const PRESSED_BUTTOM: Color = Color::rgb(0.35, 0.75, 0.35);
 
 fn button_system(
     mut q_interaction: Query<
-        (&Interaction, &mut UiColor, &Children, Option<&RemoveButton>),
+        (&Interaction, &mut UiColor, &Children, &MenuButton),
         (Changed<Interaction>, With<Button>),
     >,
     q_child: Query<&ButtonInfo>,
     mut q_text: Query<&mut Text>,
-    mut ev_start: EventWriter<StartSpawningEnemiesEvent>,
-    mut ev_remove: EventWriter<RemoveMenuEvent>,
+    mut state: ResMut<State<GameState>>,
 ) {
-    for (interaction, mut color, children, start) in &mut q_interaction {
+    for (interaction, mut color, children, button) in &mut q_interaction {
         let mut text = q_text.get_mut(children[0]).unwrap();
         let info = q_child.get(children[0]);
         match *interaction {
@@ -64,11 +67,14 @@ fn button_system(
                 text.sections[0].value = "Press".to_string();
                 *color = PRESSED_BUTTOM.into();
                 println!("Button pressed");
-                if start.is_some() {
-                    ev_start.send(StartSpawningEnemiesEvent);
-                    // don't let the player click through the menu
-                }
-                ev_remove.send(RemoveMenuEvent);
+                // ignore it if something else already changed the state this frame
+                let _ = match button {
+                    MenuButton::Start => state.set(GameState::Playing),
+                    // back to the game underneath
+                    MenuButton::Continue => state.pop(),
+                    // back to a fresh start menu
+                    MenuButton::Restart => state.replace(GameState::Menu),
+                };
             }
             Interaction::Hovered => {
                 text.sections[0].value = info.unwrap().hovered_text.clone();
@@ -82,12 +88,67 @@ fn button_system(
     }
 }
 
-fn allow_input(
-    mut ev_start: EventReader<StartSpawningEnemiesEvent>,
-    mut accept: ResMut<AcceptInput>,
-) {
-    for _ev in ev_start.iter() {
-        accept.0 = true;
+#[derive(Component)]
+struct PauseMenu;
+
+// dims the game underneath
+fn pause_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+            ..default()
+        })
+        .insert(PauseMenu)
+        .with_children(|center| {
+            for (button, base_text, hovered_text) in [
+                (MenuButton::Continue, "Paused", "Resume"),
+                (MenuButton::Restart, "Restart", "New Game"),
+            ] {
+                center
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Percent(10.0), Val::Percent(10.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            margin: UiRect::all(Val::Percent(1.0)),
+                            ..default()
+                        },
+                        color: NORMAL_BUTTOM.into(),
+                        ..default()
+                    })
+                    .insert(button)
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(TextBundle::from_section(
+                                "Button",
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: 40.0,
+                                    color: Color::rgb(0.9, 0.9, 0.9),
+                                },
+                            ))
+                            .insert(ButtonInfo {
+                                base_text: base_text.to_string(),
+                                hovered_text: hovered_text.to_string(),
+                            });
+                    });
+            }
+        });
+}
+
+fn remove_pause_menu(mut commands: Commands, q_menu: Query<Entity, With<PauseMenu>>) {
+    for ent in q_menu.iter() {
+        commands.entity(ent).despawn_recursive();
     }
 }
 
@@ -136,7 +197,7 @@ fn start_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
                         color: NORMAL_BUTTOM.into(),
                         ..default()
                     })
-                    .insert(RemoveButton)
+                    .insert(MenuButton::Start)
                     .with_children(|parent| {
                         parent
                             .spawn_bundle(TextBundle::from_section(
@@ -171,103 +232,153 @@ fn start_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
         });
 }
 
-fn remove_start_menu(
-    mut commands: Commands,
-    mut ev_remove: EventReader<RemoveMenuEvent>,
-    q_menu: Query<Entity, Or<(With<StartMenu>, With<EndMenu>)>>,
-) {
-    for _ev in ev_remove.iter() {
-        for ent in q_menu.iter() {
-            commands.entity(ent).despawn_recursive();
-        }
+fn remove_start_menu(mut commands: Commands, q_menu: Query<Entity, With<StartMenu>>) {
+    for ent in q_menu.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+}
+
+fn remove_end_menu(mut commands: Commands, q_menu: Query<Entity, With<EndMenu>>) {
+    for ent in q_menu.iter() {
+        commands.entity(ent).despawn_recursive();
     }
 }
 
-fn win_menu(
-    mut commands: Commands,
-    mut ev_boss: EventReader<BossCapEvent>,
-    asset_server: Res<AssetServer>,
+fn win_game(mut ev_boss: EventReader<BossCapEvent>, mut state: ResMut<State<GameState>>) {
+    if ev_boss.iter().count() > 0 {
+        // Continue goes back to this game
+        let _ = state.push(GameState::Won);
+    }
+}
+
+fn win_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let title = asset_server.load("sprites/Title.png");
+    end_menu(
+        &mut commands,
+        asset_server.load("fonts/FiraSans-Bold.ttf"),
+        vec![
+            (MenuButton::Continue, "You Win", "Continue"),
+            (MenuButton::Restart, "Restart", "New Game"),
+        ],
+        |center| {
+            center.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(500.0), Val::Px(76.0)),
+                    margin: UiRect::new(
+                        Val::Auto,
+                        Val::Auto,
+                        Val::Percent(10.0),
+                        Val::Percent(10.0),
+                    ),
+                    ..default()
+                },
+                image: UiImage(title),
+                ..default()
+            });
+        },
+    );
+}
+
+fn lose_menu(mut commands: Commands, asset_server: Res<AssetServer>, lives: Res<Lives>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let reason = lives.lost_to.map_or("", |r| r.description());
+    end_menu(
+        &mut commands,
+        font.clone(),
+        vec![(MenuButton::Restart, "You Lose", "Try Again")],
+        |center| {
+            center.spawn_bundle(TextBundle {
+                text: Text::from_section(
+                    reason,
+                    TextStyle {
+                        font,
+                        font_size: 30.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                style: Style {
+                    margin: UiRect::new(Val::Auto, Val::Auto, Val::Percent(5.0), Val::Percent(5.0)),
+                    ..default()
+                },
+                ..default()
+            });
+        },
+    );
+}
+
+// buttons in a column, then whatever goes under them
+fn end_menu(
+    commands: &mut Commands,
+    font: Handle<Font>,
+    buttons: Vec<(MenuButton, &str, &str)>,
+    subtitle: impl FnOnce(&mut ChildBuilder),
 ) {
-    for _ev in ev_boss.iter() {
-        commands
-            .spawn_bundle(NodeBundle {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_content: AlignContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(EndMenu)
+        .with_children(|root| {
+            root.spawn_bundle(NodeBundle {
                 style: Style {
                     size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
                     justify_content: JustifyContent::Center,
-                    align_content: AlignContent::Center,
                     align_items: AlignItems::Center,
+                    flex_direction: FlexDirection::Column,
                     ..default()
                 },
                 color: Color::NONE.into(),
                 ..default()
             })
-            .insert(EndMenu)
-            .with_children(|root| {
-                root.spawn_bundle(NodeBundle {
-                    style: Style {
-                        size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
-                        justify_content: JustifyContent::Center,
-                        //align_content: AlignContent::Center,
-                        align_items: AlignItems::Center,
-                        flex_direction: FlexDirection::Column,
-
-                        ..default()
-                    },
-                    color: Color::NONE.into(), //:BEIGE.into(),
-                    ..default()
-                })
-                .with_children(|center| {
+            .with_children(|center| {
+                for (i, (button, base_text, hovered_text)) in buttons.into_iter().enumerate() {
+                    // a gap above all but the first
+                    let gap = if i == 0 { 0.0 } else { 2.0 };
                     center
                         .spawn_bundle(ButtonBundle {
                             style: Style {
-                                // default is 1280x720
-                                // 150/1280 = 11.7%
-                                // 65/720 = 9%
-                                // 12% of 40% = 30%
-                                //size: Size::new(Val::Px(150.0), Val::Px(65.0)),
                                 size: Size::new(Val::Percent(10.0), Val::Percent(10.0)),
                                 justify_content: JustifyContent::Center,
                                 align_items: AlignItems::Center,
+                                margin: UiRect::new(
+                                    Val::Auto,
+                                    Val::Auto,
+                                    Val::Percent(gap),
+                                    Val::Percent(0.0),
+                                ),
                                 ..default()
                             },
                             color: NORMAL_BUTTOM.into(),
                             ..default()
                         })
-                        //.insert(RemoveButton)
+                        .insert(button)
                         .with_children(|parent| {
                             parent
                                 .spawn_bundle(TextBundle::from_section(
                                     "Button",
                                     TextStyle {
-                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        font: font.clone(),
                                         font_size: 40.0,
                                         color: Color::rgb(0.9, 0.9, 0.9),
                                     },
                                 ))
                                 .insert(ButtonInfo {
-                                    base_text: "You Win".to_string(),
-                                    hovered_text: "Continue".to_string(),
+                                    base_text: base_text.to_string(),
+                                    hovered_text: hovered_text.to_string(),
                                 });
                         });
-                    center.spawn_bundle(NodeBundle {
-                        style: Style {
-                            //size: Size::new(Val::Percent(100.0), Val::Percent(30.0)),
-                            size: Size::new(Val::Px(500.0), Val::Px(76.0)),
-
-                            margin: UiRect::new(
-                                Val::Auto,
-                                Val::Auto,
-                                Val::Percent(10.0),
-                                Val::Percent(10.0),
-                            ),
-                            ..default()
-                        },
-                        image: UiImage(asset_server.load("sprites/Title.png")),
-                        ..default()
-                    });
-                });
+                }
+                subtitle(center);
             });
-    }
+        });
 }
 
 fn tutorial_side_bar(mut commands: Commands, asset_server: Res<AssetServer>) {
@@ -283,7 +394,7 @@ fn tutorial_side_bar(mut commands: Commands, asset_server: Res<AssetServer>) {
                 },
             ),
             TextSection::new(
-                "\nLeft Click to place a tower preview.",
+                "\nLeft Click to place a tower preview. Ctrl Click for a frost tower, which slows what it hits.",
                 TextStyle {
                     font: font.clone(),
                     font_size: 25.0,
@@ -330,6 +441,38 @@ fn tutorial_side_bar(mut commands: Commands, asset_server: Res<AssetServer>) {
                     color: Color::WHITE,
                 },
             ),
+            TextSection::new(
+                "\nEnemies steal gold and run off the map with it. You lose if too much gets away or the center pile is emptied.",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 25.0,
+                    color: Color::WHITE,
+                },
+            ),
+            TextSection::new(
+                "\nShift Click or Shift Drag to select many tiles. [ and ] change the brush size. Esc clears the selection.",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 25.0,
+                    color: Color::WHITE,
+                },
+            ),
+            TextSection::new(
+                "\nWASD, the arrow keys or the middle mouse button move the camera. Scroll to zoom. F follows the boss.",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 25.0,
+                    color: Color::WHITE,
+                },
+            ),
+            TextSection::new(
+                "\nPress P to pause. The buttons in the bottom left change the game speed. F5 saves and F9 loads.",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 25.0,
+                    color: Color::WHITE,
+                },
+            ),
             TextSection::new(
                 "\n\nPress Tab to toggle this menu",
                 TextStyle {
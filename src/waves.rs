@@ -0,0 +1,415 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::de::Error as _;
+use serde::{Deserialize, Serialize};
+
+use crate::enemies::{self, Enemy, SpawnEnemyEvent};
+use crate::game_assets::GameAssets;
+use crate::game_time::{GameTick, GameTime, TickStep};
+use crate::gold::{self, SpawnGoldEvent};
+use crate::replay::{apply_commands, PlayerCommand, PlayerInput};
+use crate::rng::GameRng;
+use crate::GameState;
+
+// enemies come in from a circle this far from the center
+const SPAWN_RADIUS: f32 = 500.0;
+// calling a wave early pays a gold for every this many seconds skipped
+const EARLY_SECONDS_PER_GOLD: f32 = 3.0;
+
+pub struct WavePlugin;
+
+impl Plugin for WavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<WaveStarted>()
+            .add_event::<WaveCleared>()
+            .add_event::<CallWaveEvent>()
+            .init_resource::<WaveScript>()
+            .init_resource::<Waves>()
+            .add_startup_system(wave_text)
+            .add_system(update_wave_text)
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(reset_waves))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(wave_input.label(PlayerInput))
+                    // the bonus gold lands the same frame, every time
+                    .with_system(call_wave.after(apply_commands).before(gold::spawn_gold))
+                    // a splitter's pieces are still events for a frame
+                    .with_system(check_cleared.after(enemies::spawn_enemy)),
+            )
+            .add_system_set_to_stage(GameTick, TickStep::Timers.set().with_system(run_waves));
+    }
+}
+
+/// Every wave in a game, read from assets/waves.ron
+///
+/// Built in, like EnemyTypes, so the headless sim gets the same waves.
+#[derive(Deserialize, Clone, Debug)]
+pub struct WaveScript {
+    pub waves: Vec<Wave>,
+    // past the last wave it repeats, with this many more of every group each time
+    #[serde(default)]
+    pub growth: u32,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Wave {
+    // seconds after the wave before
+    pub delay: f32,
+    pub groups: Vec<WaveGroup>,
+}
+
+/// Enemies of one kind, coming in one after the other
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct WaveGroup {
+    // an EnemyType id
+    pub kind: String,
+    pub count: u32,
+    #[serde(default)]
+    pub from: SpawnAt,
+    // seconds between one and the next
+    #[serde(default)]
+    pub spacing: f32,
+    // seconds into the wave before the first one
+    #[serde(default)]
+    pub after: f32,
+}
+
+/// Where around the edge enemies come in. Angles are in degrees
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum SpawnAt {
+    // a new spot for every enemy
+    #[default]
+    Random,
+    Angle(f32),
+    // anywhere between the two
+    Arc(f32, f32),
+}
+
+impl SpawnAt {
+    fn angle(self, rng: &mut impl Rng) -> f32 {
+        match self {
+            SpawnAt::Random => rng.gen_range(0.0..360.0),
+            SpawnAt::Angle(angle) => angle,
+            SpawnAt::Arc(from, to) if from < to => rng.gen_range(from..to),
+            SpawnAt::Arc(from, _) => from,
+        }
+    }
+}
+
+impl WaveScript {
+    pub fn from_ron(text: &str) -> Result<Self, ron::Error> {
+        let script: WaveScript = ron::from_str(text)?;
+        // wave() repeats the last one, so there has to be one
+        if script.waves.is_empty() {
+            return Err(ron::Error::custom("a wave script needs at least one wave"));
+        }
+        Ok(script)
+    }
+
+    /// The wave at this index, counting from 0
+    ///
+    /// Once the script runs out, the last wave again, only bigger.
+    pub fn wave(&self, index: u32) -> Wave {
+        let last = self.waves.len() as u32 - 1;
+        if index <= last {
+            return self.waves[index as usize].clone();
+        }
+        let mut wave = self.waves[last as usize].clone();
+        for group in wave.groups.iter_mut() {
+            group.count += self.growth * (index - last);
+        }
+        wave
+    }
+}
+
+impl Default for WaveScript {
+    fn default() -> Self {
+        WaveScript::from_ron(include_str!("../assets/waves.ron")).expect("bad waves.ron")
+    }
+}
+
+/// An enemy from a wave that's on its way
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QueuedSpawn {
+    pub kind: String,
+    pub from: SpawnAt,
+    // seconds until it comes in
+    pub wait: f32,
+}
+
+/// How far through the script the game is
+pub struct Waves {
+    // waves started so far
+    pub started: u32,
+    // until the next wave starts on its own
+    pub countdown: Timer,
+    pub queue: Vec<QueuedSpawn>,
+    // the last wave started, until everything is dead
+    pub current: Option<u32>,
+}
+
+impl Waves {
+    pub fn new(script: &WaveScript) -> Self {
+        Waves {
+            started: 0,
+            countdown: Timer::from_seconds(script.wave(0).delay, false),
+            queue: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Lines up the next wave and starts counting down to the one after
+    ///
+    /// Returns its number, counting from 1.
+    pub fn start_next(&mut self, script: &WaveScript) -> u32 {
+        let wave = script.wave(self.started);
+        for group in wave.groups.iter() {
+            self.queue.extend((0..group.count).map(|i| QueuedSpawn {
+                kind: group.kind.clone(),
+                from: group.from,
+                wait: group.after + group.spacing * i as f32,
+            }));
+        }
+        self.started += 1;
+        self.current = Some(self.started);
+        self.countdown = Timer::from_seconds(script.wave(self.started).delay, false);
+        self.started
+    }
+
+    /// Gold for calling the next wave now
+    pub fn early_bonus(&self) -> u32 {
+        let left = self.countdown.duration() - self.countdown.elapsed();
+        (left.as_secs_f32() / EARLY_SECONDS_PER_GOLD) as u32
+    }
+}
+
+impl FromWorld for Waves {
+    fn from_world(world: &mut World) -> Self {
+        Waves::new(world.resource::<WaveScript>())
+    }
+}
+
+/// A wave's enemies started coming in
+pub struct WaveStarted {
+    pub number: u32,
+}
+
+/// Everything from the last wave started, and the ones before it, is gone
+pub struct WaveCleared {
+    pub number: u32,
+}
+
+/// Start the next wave now, for a bonus
+pub struct CallWaveEvent;
+
+fn run_waves(
+    time: Res<GameTime>,
+    script: Res<WaveScript>,
+    mut waves: ResMut<Waves>,
+    mut rng: ResMut<GameRng>,
+    mut ev_started: EventWriter<WaveStarted>,
+    mut ev_spawn_enemy: EventWriter<SpawnEnemyEvent>,
+) {
+    if waves.countdown.tick(time.delta()).just_finished() {
+        let number = waves.start_next(&script);
+        ev_started.send(WaveStarted { number });
+    }
+
+    let rng = &mut rng.enemies;
+    let delta = time.delta_seconds();
+    waves.queue.retain_mut(|spawn| {
+        spawn.wait -= delta;
+        if spawn.wait > 0.0 {
+            return true;
+        }
+        let angle = spawn.from.angle(rng).to_radians();
+        let position = Vec2::from_angle(angle) * SPAWN_RADIUS;
+        ev_spawn_enemy.send(SpawnEnemyEvent::new(position.extend(0.3), &spawn.kind));
+        false
+    });
+}
+
+// N calls the next wave early
+fn wave_input(keys: Res<Input<KeyCode>>, mut ev_command: EventWriter<PlayerCommand>) {
+    if keys.just_pressed(KeyCode::N) {
+        ev_command.send(PlayerCommand::CallWave);
+    }
+}
+
+fn call_wave(
+    script: Res<WaveScript>,
+    mut waves: ResMut<Waves>,
+    mut ev_call: EventReader<CallWaveEvent>,
+    mut ev_started: EventWriter<WaveStarted>,
+    mut ev_spawn_gold: EventWriter<SpawnGoldEvent>,
+) {
+    for _ev in ev_call.iter() {
+        let bonus = waves.early_bonus();
+        let number = waves.start_next(&script);
+        ev_started.send(WaveStarted { number });
+        // dropped on the center pile
+        for _ in 0..bonus {
+            ev_spawn_gold.send(SpawnGoldEvent {
+                position: Vec3::new(0.0, 0.0, 0.3),
+            });
+        }
+    }
+}
+
+fn check_cleared(
+    mut waves: ResMut<Waves>,
+    q_enemies: Query<(), With<Enemy>>,
+    mut ev_spawn_enemy: EventReader<SpawnEnemyEvent>,
+    mut ev_cleared: EventWriter<WaveCleared>,
+) {
+    let spawning = ev_spawn_enemy.iter().count() > 0;
+    if let Some(number) = waves.current {
+        if waves.queue.is_empty() && !spawning && q_enemies.is_empty() {
+            waves.current = None;
+            ev_cleared.send(WaveCleared { number });
+        }
+    }
+}
+
+// back to the menu. The next game starts from the first wave
+fn reset_waves(mut waves: ResMut<Waves>, script: Res<WaveScript>) {
+    *waves = Waves::new(&script);
+}
+
+#[derive(Component)]
+struct WaveText;
+
+fn wave_text(mut commands: Commands, game_assets: Res<GameAssets>) {
+    let font = game_assets.font.clone();
+
+    commands
+        .spawn_bundle(
+            TextBundle::from_sections([TextSection::from_style(TextStyle {
+                font,
+                font_size: 20.0,
+                color: Color::WHITE,
+            })])
+            .with_style(Style {
+                align_self: AlignSelf::FlexEnd,
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    // under the lives
+                    top: Val::Px(40.0),
+                    right: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(WaveText);
+}
+
+fn update_wave_text(waves: Res<Waves>, mut q_text: Query<&mut Text, With<WaveText>>) {
+    if !waves.is_changed() {
+        return;
+    }
+    let left = waves.countdown.duration() - waves.countdown.elapsed();
+    let next = format!(
+        "Wave {} in {:.0}s",
+        waves.started + 1,
+        left.as_secs_f32().ceil()
+    );
+    let value = match waves.early_bonus() {
+        0 => next,
+        bonus => format!("{next}\nN: call it now for {bonus} gold"),
+    };
+    for mut text in q_text.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemies::{DamageEvent, Health};
+    use crate::enemy_types::EnemyTypes;
+    use crate::test_app::TestApp;
+
+    #[test]
+    fn shipped_waves_make_sense() {
+        let script = WaveScript::default();
+        let types = EnemyTypes::default();
+        assert!(!script.waves.is_empty());
+        for wave in script.waves.iter() {
+            assert!(wave.delay > 0.0);
+            for group in wave.groups.iter() {
+                assert!(types.get(&group.kind).is_some(), "no {}", group.kind);
+            }
+        }
+    }
+
+    #[test]
+    fn a_script_without_waves_is_refused() {
+        assert!(WaveScript::from_ron("(waves: [])").is_err());
+        assert!(WaveScript::from_ron("(waves: [(delay: 5.0, groups: [])])").is_ok());
+    }
+
+    #[test]
+    fn waves_grow_past_the_end_of_the_script() {
+        let script = WaveScript::default();
+        let last = script.waves.len() as u32 - 1;
+        let count = |wave: Wave| wave.groups.iter().map(|g| g.count).sum::<u32>();
+        let groups = script.waves[last as usize].groups.len() as u32;
+        assert_eq!(
+            count(script.wave(last + 2)),
+            count(script.wave(last)) + 2 * script.growth * groups
+        );
+    }
+
+    #[test]
+    fn a_wave_starts_after_its_delay() {
+        let mut test = TestApp::new(1);
+        let delay = test.resource::<WaveScript>().wave(0).delay;
+        test.seconds(delay - 0.5);
+        assert_eq!(test.resource::<Waves>().started, 0);
+        test.seconds(1.0);
+        assert_eq!(test.resource::<Waves>().started, 1);
+        assert!(test.count::<Enemy>() > 0);
+    }
+
+    #[test]
+    fn calling_early_pays_gold() {
+        let mut test = TestApp::new(1);
+        let bonus = test.resource::<Waves>().early_bonus();
+        assert!(bonus > 0);
+        test.send(CallWaveEvent);
+        test.ticks(1);
+
+        assert_eq!(test.resource::<Waves>().started, 1);
+        assert_eq!(test.count::<gold::Gold>(), bonus as usize);
+    }
+
+    #[test]
+    fn killing_everything_clears_the_wave() {
+        let mut test = TestApp::new(1);
+        test.send(CallWaveEvent);
+        let wave = test.resource::<WaveScript>().wave(0);
+        let last = wave
+            .groups
+            .iter()
+            .map(|g| g.after + g.spacing * g.count as f32)
+            .fold(0.0, f32::max);
+        test.seconds(last + 0.1);
+
+        let enemies: Vec<Entity> = test
+            .app
+            .world
+            .query_filtered::<Entity, With<Health>>()
+            .iter(&test.app.world)
+            .collect();
+        for target in enemies {
+            test.send(DamageEvent {
+                target,
+                amount: 1000,
+                status: None,
+            });
+        }
+        test.ticks(3);
+        assert_eq!(test.resource::<Waves>().current, None);
+    }
+}